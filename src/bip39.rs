@@ -1,10 +1,65 @@
 use bdk::keys::bip39::{Language, Mnemonic};
+use std::borrow::Cow;
 
-const LANG: Language = Language::English; // TODO: hardcoded mnemonic language english for now
+use crate::Error;
 
-pub fn parse_mnemonic(mnemonic: &str) -> Mnemonic {
-    match Mnemonic::parse_in(LANG, mnemonic) {
-        Ok(m) => m,
-        Err(e) => panic!("Invalid mnemonic: {}", e),
+/// Creates a mnemonic language from a string.
+pub fn create_language(language: &str) -> Result<Language, Error> {
+    match language.to_lowercase().as_str() {
+        "english" => Ok(Language::English),
+        "simplified-chinese" => Ok(Language::SimplifiedChinese),
+        "traditional-chinese" => Ok(Language::TraditionalChinese),
+        "czech" => Ok(Language::Czech),
+        "french" => Ok(Language::French),
+        "italian" => Ok(Language::Italian),
+        "japanese" => Ok(Language::Japanese),
+        "korean" => Ok(Language::Korean),
+        "spanish" => Ok(Language::Spanish),
+        _ => Err(Error::Mnemonic(format!("unknown language {}", language))),
+    }
+}
+
+/// Parses a mnemonic from a string in the given language.
+/// If no language is given, every wordlist is tried until one matches.
+pub fn parse_mnemonic(mnemonic: &str, language: Option<Language>) -> Result<Mnemonic, Error> {
+    let languages = match language {
+        Some(ref language) => std::slice::from_ref(language),
+        None => Language::all(),
+    };
+    for language in languages {
+        if let Ok(m) = Mnemonic::parse_in(*language, mnemonic) {
+            return Ok(m);
+        }
+    }
+    Err(explain_invalid_mnemonic(mnemonic, languages))
+}
+
+/// Explains why a mnemonic is invalid using the language whose wordlist contains most of its words.
+fn explain_invalid_mnemonic(mnemonic: &str, languages: &[Language]) -> Error {
+    let mut normalized = Cow::Borrowed(mnemonic);
+    Mnemonic::normalize_utf8_cow(&mut normalized);
+    let words: Vec<&str> = normalized.split_whitespace().collect();
+
+    // words of the mnemonic that are not found in the wordlist of each language
+    let unknown_words = languages.iter().map(|language| {
+        let word_list = language.word_list();
+        let unknown: Vec<&str> = words
+            .iter()
+            .filter(|word| !word_list.contains(word))
+            .copied()
+            .collect();
+        (language, unknown)
+    });
+    match unknown_words.min_by_key(|(_, unknown)| unknown.len()) {
+        Some((language, unknown)) if !unknown.is_empty() => Error::Mnemonic(format!(
+            "words not found in the {} wordlist: {}",
+            language,
+            unknown.join(", ")
+        )),
+        Some((language, _)) => match Mnemonic::parse_in(*language, mnemonic) {
+            Err(e) => Error::Mnemonic(e.to_string()),
+            Ok(_) => Error::Mnemonic(format!("invalid mnemonic in the {} wordlist", language)),
+        },
+        None => Error::Mnemonic("no languages to parse the mnemonic".to_string()),
     }
 }
@@ -1,35 +1,181 @@
+use bdk::{
+    bitcoin::{Network, Txid},
+    keys::bip39::MnemonicWithPassphrase,
+    wallet::Wallet,
+};
 use bdk_chain::bitcoin::util::bip32::DerivationPath;
-use clap::Parser;
+use bitcoincore_rpc::Auth;
+use clap::{value_parser, Parser, Subcommand};
 use futures::future::join_all;
 use rayon::prelude::*;
+use rpassword::prompt_password;
+use std::{
+    fs::read_to_string,
+    io::{stdin, stdout, Read, Write},
+    path::{Path, PathBuf},
+};
 
 use crate::{
-    bip39::parse_mnemonic,
-    network::{create_client, create_network},
+    bip39::{create_language, parse_mnemonic},
+    network::{create_backend, create_network, require_tor, Backend},
+    store::{default_data_dir, read_last_sweep, write_last_sweep, WalletStore},
     wallet::{
-        broadcast_signed_transaction, check_balance, create_address,
-        create_derivation_paths_with_last_index, create_signed_transaction, create_wallet,
-        sync_wallet, DERIVATION_PATHS,
+        address_warning, below_fee, broadcast_signed_transaction, check_balance,
+        check_destinations, check_destinations_backend, create_consolidated_transaction,
+        create_cpfp_transaction, create_derivation_paths_with_last_index, create_destination,
+        create_destination_wallet, create_fee_bump_transaction, create_payee,
+        create_signed_transaction, create_wallet, derive_destinations, get_account, get_coin_type,
+        has_history, left_behind, sweepable_coins, sync_wallet, sync_wallets, with_account,
+        Destination, FeeStrategy, Payee, Share, Sweep, DERIVATION_PATHS,
     },
+    Error,
 };
 
 /// Bitcoin address generator
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
-struct Args {
-    /// Seed to sweep funds from
-    seed: String,
+pub struct Args {
+    /// Seed to sweep funds from, visible in the shell history and to other users,
+    /// prefer --seed-file, --seed-stdin or the prompt shown when it is omitted
+    seed: Option<String>,
+
+    /// Address to withdraw to, or an output descriptor or xpub to derive fresh unused addresses from
+    address: Option<String>,
+
+    /// Destination to split the sweep across, as address:amount in sats or address:percent%,
+    /// repeated for every destination, the last one receiving the remainder without a share,
+    /// where the address can also be an output descriptor or xpub
+    #[arg(long, conflicts_with = "address")]
+    to: Vec<String>,
+
+    /// Read the seed from a file
+    #[arg(long, conflicts_with = "seed_stdin", global = true)]
+    seed_file: Option<PathBuf>,
 
-    /// Address to withdraw to
-    address: String,
+    /// Read the seed from stdin, requires --yes or --dry-run since stdin is no longer available
+    #[arg(long, global = true)]
+    seed_stdin: bool,
+
+    /// Ask for a BIP39 passphrase
+    #[arg(short, long, global = true)]
+    passphrase: bool,
+
+    /// Language of the seed (english, simplified-chinese, traditional-chinese, czech,
+    /// french, italian, japanese, korean or spanish), detected from the words if not given
+    #[arg(short, long, global = true)]
+    language: Option<String>,
 
     /// Network to use
-    #[arg(short, long, default_value = "mainnet")]
+    #[arg(short, long, default_value = "mainnet", global = true)]
     network: String,
 
-    /// Esplora server to use
-    #[arg(short, long, default_value = "https://mempool.space/api")]
+    /// Scan both the mainnet and the testnet coin types, for wallets that used the wrong one
+    #[arg(long, global = true)]
+    both_coin_types: bool,
+
+    /// Number of consecutive accounts without history to scan before stopping account discovery
+    #[arg(long, default_value_t = 1, value_parser = value_parser!(u32).range(1..), global = true)]
+    account_gap: u32,
+
+    /// Backend to use (esplora, electrum, rpc or cbf)
+    #[arg(short, long, default_value = "esplora", global = true)]
+    backend: String,
+
+    /// Server of the backend to use, such as ssl://electrum.blockstream.info:50002 for electrum,
+    /// http://localhost:8332 for rpc or localhost:8333 for cbf
+    #[arg(
+        short,
+        long,
+        default_value = "https://mempool.space/api",
+        global = true
+    )]
     url: String,
+
+    /// SOCKS5 proxy to connect to the backend through, such as socks5h://127.0.0.1:9050 for Tor
+    #[arg(long, global = true)]
+    proxy: Option<String>,
+
+    /// Refuse to connect unless through a socks5h proxy, so that nothing leaks outside of Tor
+    #[arg(long, global = true)]
+    require_tor: bool,
+
+    /// Cookie file to authenticate to the rpc backend
+    #[arg(long, conflicts_with = "rpc_user", global = true)]
+    rpc_cookie: Option<PathBuf>,
+
+    /// User to authenticate to the rpc backend, asking for its password
+    #[arg(long, global = true)]
+    rpc_user: Option<String>,
+
+    /// Directory to cache the synced wallets in [default: the user cache directory]
+    #[arg(long, global = true)]
+    data_dir: Option<PathBuf>,
+
+    /// Keep the wallets in memory only, without writing anything to disk
+    #[arg(long, conflicts_with = "data_dir", global = true)]
+    ephemeral: bool,
+
+    /// Fee rate in sat/vB [default: the estimate to confirm in the next block]
+    #[arg(long, conflicts_with_all = ["target_blocks", "absolute_fee"], global = true)]
+    fee_rate: Option<f64>,
+
+    /// Number of blocks to confirm within, to estimate the fee rate with the backend
+    #[arg(long, conflicts_with = "absolute_fee", value_parser = value_parser!(u64).range(1..), global = true)]
+    target_blocks: Option<u64>,
+
+    /// Absolute fee in sats of each transaction
+    #[arg(long, value_parser = value_parser!(u64).range(1..), global = true)]
+    absolute_fee: Option<u64>,
+
+    /// Maximum fee rate in sat/vB, refusing to sweep above it to prevent overpaying by mistake
+    #[arg(long, default_value_t = 1000.0, global = true)]
+    max_fee_rate: f64,
+
+    /// Sweep all wallets into a single transaction
+    #[arg(short, long)]
+    consolidate: bool,
+
+    /// Also sweep unconfirmed coins, which are lost to the sweep if their transaction is replaced
+    #[arg(long)]
+    include_unconfirmed: bool,
+
+    /// Also sweep coins that cost more to spend than they are worth at the fee rate
+    #[arg(long)]
+    include_dust: bool,
+
+    /// Print the sweep plan without broadcasting
+    #[arg(long, global = true)]
+    dry_run: bool,
+
+    /// Broadcast without asking for confirmation
+    #[arg(short, long, global = true)]
+    yes: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Commands acting on past sweeps instead of sweeping
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Bump the fee of an unconfirmed sweep by replacing it (RBF) with the fee of the fee options,
+    /// reading the seed from a file, stdin or the prompt
+    Bump {
+        /// Txid of the sweep to bump [default: the last sweep recorded in the data directory]
+        txid: Option<Txid>,
+    },
+    /// Accelerate an unconfirmed sweep to a wallet of ours with a child transaction (CPFP)
+    /// paying enough fee for the package to reach the fee of the fee options,
+    /// reading the descriptor of the destination with its private keys from a file or the prompt
+    Cpfp {
+        /// Txid of the sweep to accelerate [default: the last sweep recorded in the data directory]
+        txid: Option<Txid>,
+
+        /// Read the descriptor of the destination, or the extended private key of its account,
+        /// from a file
+        #[arg(long)]
+        descriptor_file: Option<PathBuf>,
+    },
 }
 
 /// Parse command line arguments
@@ -39,58 +185,706 @@ fn parse() -> Args {
 
 /// Run the CLI
 /// 1. Parse command line arguments
-/// 2. Parse mnemonic
-/// 3. Create address from string
-/// 4. Create network from string
+/// 2. Create network from string
+/// 3. Create the destinations from the address, descriptor or the `--to` options,
+///    checking their shares and that they belong to the network
+/// 4. Read and parse mnemonic, and ask for the passphrase if needed
 /// 5. Create derivation paths from the specified derivation paths (11 by default)
-/// 6. Create wallets from the specified derivation paths
-/// 7. Create an Espora client from the specified url
+///    with the coin type of the network, or both coin types if asked to
+/// 6. Create wallets from the specified derivation paths, in memory only if `--ephemeral`
+/// 7. Create an Esplora, Electrum, Bitcoin Core RPC or compact block filters client
+///    from the specified url, through the proxy if any
 /// 8. Sync wallets
-/// 9. Check balance of wallets
-/// 10. Create signed transactions for wallets that have balance
-/// 11. Broadcast signed transactions to the Espora server
-pub async fn run() {
-    let args = parse();
-    let seed = parse_mnemonic(&args.seed);
-    let address = create_address(&args.address);
-    let network = create_network(&args.network);
+/// 9. Discover further accounts until `--account-gap` consecutive accounts have no history
+/// 10. Check balance of wallets, explaining which coins are left behind and why,
+///     such as the coins that cost more to spend than they are worth unless `--include-dust`
+/// 11. Derive a fresh unused address for every output to a descriptor destination,
+///     and create signed transactions for wallets that have balance,
+///     or a single signed transaction for all of them if consolidating,
+///     with the given fee rate, confirmation target or absolute fee
+/// 12. Print the signed transactions and stop if it is a dry run
+/// 13. Ask for confirmation unless `--yes` is given
+/// 14. Broadcast signed transactions with the backend,
+///     recording their txids in the data directory unless `--ephemeral`
+///
+/// The `bump` subcommand replaces a sweep with a higher fee instead of steps 3 and 10 to 11,
+/// and the `cpfp` subcommand accelerates a sweep from the wallet of the destination instead.
+pub async fn run() -> Result<(), Error> {
+    run_with(parse()).await
+}
+
+/// Run the CLI with already parsed command line arguments, see [`run`]
+pub async fn run_with(args: Args) -> Result<(), Error> {
+    if args.seed_stdin && !args.yes && !args.dry_run {
+        return Err(Error::Io(
+            "--seed-stdin requires --yes or --dry-run".to_string(),
+        ));
+    }
+    if args.require_tor {
+        require_tor(args.proxy.as_deref())?;
+    }
+    let fee = fee_strategy(&args)?;
+    // fail before syncing rather than once the fee rate has to be estimated
+    if args.backend.eq_ignore_ascii_case("cbf") && matches!(fee, FeeStrategy::Target(_)) {
+        return Err(Error::FeeEstimates(
+            "the cbf backend does not provide fee estimates, use --fee-rate or --absolute-fee"
+                .to_string(),
+        ));
+    }
+    match &args.command {
+        Some(Command::Bump { txid }) => return bump(&args, *txid, fee).await,
+        Some(Command::Cpfp {
+            txid,
+            descriptor_file,
+        }) => return cpfp(&args, *txid, descriptor_file.as_deref(), fee).await,
+        None => {}
+    }
+    let network = create_network(&args.network)?;
+    let (seed, destinations) = read_seed_and_destinations(&args, network)?;
+    let language = args.language.as_deref().map(create_language).transpose()?;
+    let seed = (parse_mnemonic(&seed, language)?, read_passphrase(&args)?);
+    let data_dir = data_dir(&args);
+    let backend = create_backend(
+        &args.backend,
+        &args.url,
+        network,
+        read_rpc_auth(&args)?,
+        data_dir.as_deref(),
+        args.proxy.as_deref(),
+    )?;
+    check_destinations_backend(&destinations, &backend)?;
+    let wallets = discover_wallets(&args, &seed, network, data_dir.as_deref(), &backend).await?;
+
+    // estimate the fee rate once, for every sweep and to tell which coins are not worth sweeping
+    let fee = if wallets
+        .iter()
+        .any(|(_, wallet)| check_balance(wallet, args.include_unconfirmed))
+    {
+        fee.resolve(&backend).await?
+    } else {
+        fee
+    };
+    let dust_fee_rate = if args.include_dust {
+        None
+    } else {
+        fee.fee_rate()
+    };
+    print_left_behind(&wallets, args.include_unconfirmed, dust_fee_rate);
+
+    // filter wallets that have coins worth sweeping
+    let wallets: Vec<_> = wallets
+        .into_par_iter()
+        .filter(|(_, wallet)| {
+            !sweepable_coins(wallet, args.include_unconfirmed, dust_fee_rate).is_empty()
+        })
+        .collect();
+    // and leave behind the coins that do not cover the fee of the whole sweep, instead of failing it
+    let below_fee_of = |wallets: &[&Wallet<WalletStore>]| {
+        below_fee(
+            wallets,
+            &destinations,
+            args.include_unconfirmed,
+            dust_fee_rate,
+            fee,
+        )
+    };
+    let (paths_with_balance, mut wallets_with_balance): (Vec<_>, Vec<_>) = if args.consolidate {
+        let all: Vec<_> = wallets.iter().map(|(_, wallet)| wallet).collect();
+        match below_fee_of(&all) {
+            Some((value, fee)) if !wallets.is_empty() => {
+                eprintln!(
+                    "Left behind: {} sats in all wallets do not cover the fee of about {} sats \
+                     and the shares of the destinations",
+                    value, fee
+                );
+                (Vec::new(), Vec::new())
+            }
+            _ => wallets.into_iter().unzip(),
+        }
+    } else {
+        wallets
+            .into_iter()
+            .filter(|(path, wallet)| match below_fee_of(&[wallet]) {
+                Some((value, fee)) => {
+                    eprintln!(
+                        "Left behind in {}: {} sats do not cover the fee of about {} sats \
+                         and the shares of the destinations",
+                        path, value, fee
+                    );
+                    false
+                }
+                None => true,
+            })
+            .unzip()
+    };
+    let fixed_amount = destinations
+        .iter()
+        .any(|destination| matches!(destination.share, Some(Share::Amount(_))));
+    if fixed_amount && !args.consolidate && wallets_with_balance.len() > 1 {
+        return Err(Error::Address(format!(
+            "each of the {} wallets with coins would send the fixed amounts, use --consolidate",
+            wallets_with_balance.len()
+        )));
+    }
+
+    // parallel async transaction creation
+    if !wallets_with_balance.is_empty() {
+        let sweep_count = if args.consolidate {
+            1
+        } else {
+            wallets_with_balance.len()
+        };
+        let sweep_destinations = derive_destinations(
+            &destinations,
+            sweep_count,
+            network,
+            data_dir.as_deref(),
+            &backend,
+        )
+        .await?;
+        let sweeps: Vec<(Vec<DerivationPath>, Sweep)> = if args.consolidate {
+            let sweep = create_consolidated_transaction(
+                &mut wallets_with_balance,
+                &sweep_destinations[0],
+                &backend,
+                fee,
+                args.max_fee_rate,
+                args.include_unconfirmed,
+                args.include_dust,
+            )
+            .await?;
+            vec![(paths_with_balance, sweep)]
+        } else {
+            let sweeps = join_all(
+                wallets_with_balance
+                    .iter_mut()
+                    .zip(&sweep_destinations)
+                    .map(|(wallet, destinations)| {
+                        create_signed_transaction(
+                            wallet,
+                            destinations,
+                            &backend,
+                            fee,
+                            args.max_fee_rate,
+                            args.include_unconfirmed,
+                            args.include_dust,
+                        )
+                    })
+                    .collect::<Vec<_>>(),
+            )
+            .await
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+            paths_with_balance
+                .into_iter()
+                .map(|path| vec![path])
+                .zip(sweeps)
+                .collect()
+        };
+
+        if args.dry_run {
+            for (paths, sweep) in &sweeps {
+                print_sweep(paths, sweep);
+            }
+            return Ok(());
+        }
+
+        if !args.yes {
+            confirm(&sweeps)?;
+        }
+
+        let txids = broadcast_sweeps(sweeps, &backend).await;
+        let broadcasted: Vec<Txid> = txids.iter().flatten().copied().collect();
+        if let Some(data_dir) = &data_dir {
+            if !broadcasted.is_empty() {
+                write_last_sweep(data_dir, network, &broadcasted)?;
+            }
+        }
+        txids.into_iter().collect::<Result<Vec<_>, _>>()?;
+    } else {
+        eprintln!("No coins to sweep");
+    }
+    Ok(())
+}
+
+/// Bump the fee of a sweep, or of every transaction of the last sweep recorded in the data directory,
+/// replacing their txids in the record with the txids of the replacements
+async fn bump(args: &Args, txid: Option<Txid>, fee: FeeStrategy) -> Result<(), Error> {
+    require_mempool(args, "bump")?;
+    let network = create_network(&args.network)?;
+    let data_dir = data_dir(args);
+    let txids = sweep_txids(txid, data_dir.as_deref(), network)?;
+    let language = args.language.as_deref().map(create_language).transpose()?;
+    let seed = (
+        parse_mnemonic(&read_seed(args)?, language)?,
+        read_passphrase(args)?,
+    );
+    let backend = create_backend(
+        &args.backend,
+        &args.url,
+        network,
+        read_rpc_auth(args)?,
+        data_dir.as_deref(),
+        args.proxy.as_deref(),
+    )?;
+    let (paths, mut wallets): (Vec<_>, Vec<_>) =
+        discover_wallets(args, &seed, network, data_dir.as_deref(), &backend)
+            .await?
+            .into_iter()
+            .unzip();
+
+    let mut sweeps = Vec::new();
+    for txid in &txids {
+        // the wallets that know the sweep are the ones it spends from
+        let sweep_paths = paths
+            .iter()
+            .zip(&wallets)
+            .filter(|(_, wallet)| wallet.get_tx(*txid, false).is_some())
+            .map(|(path, _)| path.clone())
+            .collect();
+        let sweep =
+            create_fee_bump_transaction(&mut wallets, *txid, &backend, fee, args.max_fee_rate)
+                .await?;
+        sweeps.push((sweep_paths, sweep));
+    }
+
+    if args.dry_run {
+        for (paths, sweep) in &sweeps {
+            print_sweep(paths, sweep);
+        }
+        return Ok(());
+    }
+
+    if !args.yes {
+        confirm(&sweeps)?;
+    }
+
+    let replacements = broadcast_sweeps(sweeps, &backend).await;
+    if let Some(data_dir) = &data_dir {
+        if let Ok(last_sweep) = read_last_sweep(data_dir, network) {
+            let replaced: Vec<Txid> = last_sweep
+                .iter()
+                .map(|txid| {
+                    match txids
+                        .iter()
+                        .zip(&replacements)
+                        .find(|(bumped, _)| *bumped == txid)
+                    {
+                        Some((_, Ok(replacement))) => *replacement,
+                        _ => *txid,
+                    }
+                })
+                .collect();
+            if replaced != last_sweep {
+                write_last_sweep(data_dir, network, &replaced)?;
+            }
+        }
+    }
+    replacements.into_iter().collect::<Result<Vec<_>, _>>()?;
+    Ok(())
+}
+
+/// Accelerate a sweep, or every transaction of the last sweep recorded in the data directory,
+/// with a child transaction from the wallet of the destination
+async fn cpfp(
+    args: &Args,
+    txid: Option<Txid>,
+    descriptor_file: Option<&Path>,
+    fee: FeeStrategy,
+) -> Result<(), Error> {
+    require_mempool(args, "cpfp")?;
+    let network = create_network(&args.network)?;
+    let data_dir = data_dir(args);
+    let txids = sweep_txids(txid, data_dir.as_deref(), network)?;
+    let descriptor = read_descriptor(descriptor_file)?;
+    let backend = create_backend(
+        &args.backend,
+        &args.url,
+        network,
+        read_rpc_auth(args)?,
+        data_dir.as_deref(),
+        args.proxy.as_deref(),
+    )?;
+    let mut wallet = create_destination_wallet(&descriptor, network, data_dir.as_deref())?;
+    sync_wallet(&mut wallet, &backend).await?;
+
+    let mut sweeps = Vec::new();
+    for txid in txids {
+        let sweep =
+            create_cpfp_transaction(&mut wallet, txid, &backend, fee, args.max_fee_rate).await?;
+        sweeps.push((Vec::new(), sweep));
+    }
+
+    if args.dry_run {
+        for (paths, sweep) in &sweeps {
+            print_sweep(paths, sweep);
+        }
+        return Ok(());
+    }
+
+    if !args.yes {
+        confirm(&sweeps)?;
+    }
+
+    broadcast_sweeps(sweeps, &backend)
+        .await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(())
+}
+
+/// Refuse the rpc and cbf backends for the subcommands acting on an unconfirmed sweep,
+/// since neither ever puts a transaction of the mempool into the wallets
+fn require_mempool(args: &Args, command: &str) -> Result<(), Error> {
+    match args.backend.to_lowercase().as_str() {
+        backend @ ("rpc" | "cbf") => Err(Error::Client(format!(
+            "the {} backend does not see unconfirmed sweeps, {} needs esplora or electrum",
+            backend, command
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// The txids of the sweep to act on, the given one or the last sweep recorded in the data directory
+fn sweep_txids(
+    txid: Option<Txid>,
+    data_dir: Option<&Path>,
+    network: Network,
+) -> Result<Vec<Txid>, Error> {
+    match (txid, data_dir) {
+        (Some(txid), _) => Ok(vec![txid]),
+        (None, Some(data_dir)) => read_last_sweep(data_dir, network),
+        (None, None) => Err(Error::Transaction(
+            "no sweep is recorded with --ephemeral, give its txid".to_string(),
+        )),
+    }
+}
+
+/// The data directory from the command line arguments, none if `--ephemeral`
+fn data_dir(args: &Args) -> Option<PathBuf> {
+    if args.ephemeral {
+        None
+    } else {
+        Some(args.data_dir.clone().unwrap_or_else(default_data_dir))
+    }
+}
+
+/// Create and sync the wallets of the specified derivation paths,
+/// discovering further accounts until `--account-gap` consecutive accounts have no history
+async fn discover_wallets(
+    args: &Args,
+    seed: &MnemonicWithPassphrase,
+    network: Network,
+    data_dir: Option<&Path>,
+    backend: &Backend,
+) -> Result<Vec<(DerivationPath, Wallet<WalletStore<'static>>)>, Error> {
+    let coin_types = if args.both_coin_types {
+        vec![0, 1]
+    } else {
+        vec![get_coin_type(network)]
+    };
     let derivation_paths: Vec<(DerivationPath, DerivationPath)> = DERIVATION_PATHS
         .par_iter()
-        .map(|path| create_derivation_paths_with_last_index(path))
+        .flat_map(|path| {
+            coin_types
+                .par_iter()
+                .map(move |coin_type| create_derivation_paths_with_last_index(path, *coin_type))
+        })
+        .collect::<Result<_, _>>()?;
+    let data_dir = data_dir.filter(|_| backend.persists_wallets());
+    let mut wallets = create_wallets(seed, network, &derivation_paths, data_dir)?;
+    sync_wallets(
+        wallets.iter_mut().map(|(_, wallet)| wallet).collect(),
+        backend,
+    )
+    .await?;
+
+    // account discovery for the derivation paths of the first account,
+    // every round scans the next account of the paths that are still within the gap
+    let mut frontier: Vec<_> = derivation_paths
+        .into_iter()
+        .zip(&wallets)
+        .filter(|((external, _), _)| get_account(external) == Some(0))
+        .map(|(paths, (_, wallet))| (paths, if has_history(wallet) { 0 } else { 1 }))
+        .filter(|(_, gap)| *gap < args.account_gap)
         .collect();
-    let mut wallets: Vec<_> = derivation_paths
+    let mut account = 0;
+    while !frontier.is_empty() {
+        account += 1;
+        let account_paths = frontier
+            .iter()
+            .map(|((external, internal), _)| {
+                Ok((
+                    with_account(external, account)?,
+                    with_account(internal, account)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, Error>>()?;
+        let mut account_wallets = create_wallets(seed, network, &account_paths, data_dir)?;
+        sync_wallets(
+            account_wallets
+                .iter_mut()
+                .map(|(_, wallet)| wallet)
+                .collect(),
+            backend,
+        )
+        .await?;
+        frontier = frontier
+            .into_iter()
+            .zip(&account_wallets)
+            .map(|((paths, gap), (_, wallet))| {
+                (paths, if has_history(wallet) { 0 } else { gap + 1 })
+            })
+            .filter(|(_, gap)| *gap < args.account_gap)
+            .collect();
+        wallets.extend(account_wallets);
+    }
+    Ok(wallets)
+}
+
+/// Create the wallets of the given external and internal derivation paths in parallel,
+/// paired with their external derivation path
+fn create_wallets(
+    seed: &MnemonicWithPassphrase,
+    network: Network,
+    derivation_paths: &[(DerivationPath, DerivationPath)],
+    data_dir: Option<&Path>,
+) -> Result<Vec<(DerivationPath, Wallet<WalletStore<'static>>)>, Error> {
+    derivation_paths
         .par_iter()
         .map(|(external, internal)| {
-            create_wallet(seed.clone(), network, external.clone(), internal.clone())
+            let wallet = create_wallet(
+                seed.clone(),
+                network,
+                external.clone(),
+                internal.clone(),
+                data_dir,
+            )?;
+            Ok((external.clone(), wallet))
         })
-        .collect();
-    let client = create_client(&args.url);
+        .collect()
+}
 
-    // parallel async wallet sync
-    let tasks = wallets
-        .iter_mut()
-        .map(|wallet| sync_wallet(wallet, &client))
-        .collect::<Vec<_>>();
-    join_all(tasks).await;
+/// The fee strategy from the command line arguments,
+/// the fee rate estimated to confirm in the next block if none is given
+fn fee_strategy(args: &Args) -> Result<FeeStrategy, Error> {
+    // comparisons with NaN are always false, so non-finite values must be refused explicitly
+    if !args.max_fee_rate.is_finite() || args.max_fee_rate <= 0.0 {
+        return Err(Error::Transaction(
+            "the maximum fee rate must be a positive number".to_string(),
+        ));
+    }
+    match (args.fee_rate, args.target_blocks, args.absolute_fee) {
+        (Some(fee_rate), _, _) if !fee_rate.is_finite() || fee_rate <= 0.0 => Err(
+            Error::Transaction("the fee rate must be a positive number".to_string()),
+        ),
+        (Some(fee_rate), _, _) if fee_rate > args.max_fee_rate => Err(Error::Transaction(format!(
+            "fee rate of {:.2} sat/vB is above the maximum of {:.2} sat/vB",
+            fee_rate, args.max_fee_rate
+        ))),
+        (Some(fee_rate), _, _) => Ok(FeeStrategy::Rate(fee_rate)),
+        (None, Some(blocks), _) => Ok(FeeStrategy::Target(blocks)),
+        (None, None, Some(fee)) => Ok(FeeStrategy::Absolute(fee)),
+        (None, None, None) => Ok(FeeStrategy::default()),
+    }
+}
 
-    // filter wallets that have balance
-    let mut wallets_with_balance: Vec<_> = wallets.into_par_iter().filter(check_balance).collect();
+/// Read the seed and the destinations from the command line arguments.
+/// The destinations are either the address argument or the `--to` options,
+/// in which case a single positional argument is the seed.
+/// If a single positional argument is given without `--to` it is the address,
+/// and the seed is read from a file, stdin or a hidden prompt.
+/// The destinations are checked against the network, warning about legacy or uncommon addresses.
+fn read_seed_and_destinations(
+    args: &Args,
+    network: Network,
+) -> Result<(String, Vec<Destination>), Error> {
+    let (seed, destinations) = match (&args.seed, &args.address) {
+        (seed, None) if !args.to.is_empty() => (
+            seed.as_ref(),
+            args.to
+                .iter()
+                .map(|to| create_destination(to, network))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        (Some(seed), Some(address)) => (Some(seed), vec![create_payee(address, network)?.into()]),
+        (Some(address), None) => (None, vec![create_payee(address, network)?.into()]),
+        _ => return Err(Error::Address("missing address".to_string())),
+    };
+    check_destinations(&destinations)?;
+    for destination in &destinations {
+        if let Payee::Address(address) = &destination.payee {
+            if let Some(warning) = address_warning(address) {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+    }
+    let seed = match seed {
+        Some(_) if args.seed_file.is_some() || args.seed_stdin => {
+            return Err(Error::Mnemonic(
+                "seed given both as an argument and with --seed-file or --seed-stdin".to_string(),
+            ))
+        }
+        Some(seed) => {
+            eprintln!(
+                "Warning: the seed passed as an argument is visible in the shell history \
+                 and to other users, prefer --seed-file, --seed-stdin or the prompt"
+            );
+            seed.to_owned()
+        }
+        None => read_seed(args)?,
+    };
+    Ok((seed, destinations))
+}
 
-    // parallel async transaction creation
-    if !wallets_with_balance.is_empty() {
-        let pbsts = join_all(
-            wallets_with_balance
-                .iter_mut()
-                .map(|wallet| create_signed_transaction(wallet, address.clone(), &client))
-                .collect::<Vec<_>>(),
-        )
-        .await;
-        join_all(
-            pbsts
-                .into_iter()
-                .map(|psbt| broadcast_signed_transaction(psbt, &client))
-                .collect::<Vec<_>>(),
-        )
-        .await;
+/// Read the seed from a file, stdin or a prompt that does not echo the input
+fn read_seed(args: &Args) -> Result<String, Error> {
+    let seed = if let Some(path) = &args.seed_file {
+        read_to_string(path)
+    } else if args.seed_stdin {
+        let mut seed = String::new();
+        stdin().read_to_string(&mut seed).map(|_| seed)
+    } else {
+        prompt_password("Seed: ")
+    };
+    match seed {
+        Ok(seed) => Ok(seed.trim().to_string()),
+        Err(e) => Err(Error::Io(e.to_string())),
+    }
+}
+
+/// Ask for the BIP39 passphrase with a prompt that does not echo the input
+fn read_passphrase(args: &Args) -> Result<Option<String>, Error> {
+    if !args.passphrase {
+        return Ok(None);
+    }
+    match prompt_password("Passphrase: ") {
+        Ok(passphrase) => Ok(Some(passphrase)),
+        Err(e) => Err(Error::Io(e.to_string())),
+    }
+}
+
+/// Read the descriptor of the destination from a file or a prompt that does not echo the input
+fn read_descriptor(path: Option<&Path>) -> Result<String, Error> {
+    let descriptor = match path {
+        Some(path) => read_to_string(path),
+        None => prompt_password("Destination descriptor: "),
+    };
+    match descriptor {
+        Ok(descriptor) => Ok(descriptor.trim().to_string()),
+        Err(e) => Err(Error::Io(e.to_string())),
+    }
+}
+
+/// Read the credentials of the rpc backend, asking for the password of the user with a prompt
+/// that does not echo the input
+fn read_rpc_auth(args: &Args) -> Result<Option<Auth>, Error> {
+    if let Some(cookie) = &args.rpc_cookie {
+        return Ok(Some(Auth::CookieFile(cookie.clone())));
+    }
+    let user = match &args.rpc_user {
+        Some(user) => user.clone(),
+        None => return Ok(None),
+    };
+    match prompt_password("RPC password: ") {
+        Ok(password) => Ok(Some(Auth::UserPass(user, password))),
+        Err(e) => Err(Error::Io(e.to_string())),
+    }
+}
+
+/// Broadcast the sweeps with the backend in parallel, printing the txids of the broadcasted ones
+async fn broadcast_sweeps(
+    sweeps: Vec<(Vec<DerivationPath>, Sweep)>,
+    backend: &Backend,
+) -> Vec<Result<Txid, Error>> {
+    let txids = join_all(
+        sweeps
+            .into_iter()
+            .map(|(_, sweep)| broadcast_signed_transaction(sweep.psbt, backend))
+            .collect::<Vec<_>>(),
+    )
+    .await;
+    for txid in txids.iter().flatten() {
+        println!("Tx broadcasted! Txid: {}", txid);
+    }
+    txids
+}
+
+/// Explain which coins of the wallets are left behind by the sweep and why
+fn print_left_behind(
+    wallets: &[(DerivationPath, Wallet<WalletStore<'static>>)],
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+) {
+    for (path, wallet) in wallets {
+        let left = left_behind(wallet, include_unconfirmed, dust_fee_rate);
+        if left.immature > 0 {
+            eprintln!(
+                "Left behind in {}: {} sats of immature coinbase, spendable once 100 blocks deep",
+                path, left.immature
+            );
+        }
+        if left.unconfirmed > 0 {
+            eprintln!(
+                "Left behind in {}: {} sats unconfirmed, sweep them with --include-unconfirmed",
+                path, left.unconfirmed
+            );
+        }
+        for (outpoint, value) in left.dust {
+            eprintln!(
+                "Left behind in {}: {} of {} sats costs more to spend than it is worth \
+                 at {:.2} sat/vB, sweep it with --include-dust",
+                path,
+                outpoint,
+                value,
+                dust_fee_rate.unwrap_or_default()
+            );
+        }
+    }
+}
+
+/// Print a sweep along with the derivation paths of the wallets it spends from, if any
+fn print_sweep(paths: &[DerivationPath], sweep: &Sweep) {
+    if !paths.is_empty() {
+        let paths: Vec<String> = paths.iter().map(ToString::to_string).collect();
+        println!("Derivation paths: {}", paths.join(", "));
+    }
+    println!("{}", sweep);
+    println!();
+}
+
+/// Print a summary of the sweeps and ask the user to type `yes` to broadcast them
+fn confirm(sweeps: &[(Vec<DerivationPath>, Sweep)]) -> Result<(), Error> {
+    let amount: u64 = sweeps.iter().map(|(_, sweep)| sweep.amount()).sum();
+    let fee: u64 = sweeps.iter().map(|(_, sweep)| sweep.fee()).sum();
+    let mut addresses: Vec<String> = sweeps
+        .iter()
+        .flat_map(|(_, sweep)| {
+            sweep
+                .recipients
+                .iter()
+                .map(|(address, _)| address)
+                .chain([&sweep.address])
+        })
+        .map(ToString::to_string)
+        .collect();
+    addresses.dedup();
+
+    println!("Amount: {} sats", amount);
+    println!("Fee: {} sats", fee);
+    println!("Destination: {}", addresses.join(", "));
+    println!("Transactions: {}", sweeps.len());
+    print!("Type 'yes' to broadcast: ");
+    if let Err(e) = stdout().flush() {
+        return Err(Error::Io(e.to_string()));
+    }
+
+    let mut answer = String::new();
+    if let Err(e) = stdin().read_line(&mut answer) {
+        return Err(Error::Io(e.to_string()));
+    }
+    match answer.trim() {
+        "yes" => Ok(()),
+        _ => Err(Error::Aborted),
     }
 }
@@ -0,0 +1,80 @@
+use std::fmt;
+
+/// Errors returned by the sweepr library.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Error {
+    /// The mnemonic could not be parsed.
+    Mnemonic(String),
+    /// The network is not supported.
+    Network(String),
+    /// The backend client could not be created.
+    Client(String),
+    /// The derivation path could not be parsed.
+    DerivationPath(String),
+    /// The descriptor could not be created.
+    Descriptor(String),
+    /// The wallet could not be created or persisted.
+    Wallet(String),
+    /// The address could not be parsed.
+    Address(String),
+    /// The wallet could not be synced.
+    Sync(String),
+    /// The fee estimates could not be fetched.
+    FeeEstimates(String),
+    /// The transaction could not be created.
+    Transaction(String),
+    /// The transaction could not be signed.
+    Signing(String),
+    /// The transaction could not be broadcasted.
+    Broadcast(String),
+    /// The input could not be read.
+    Io(String),
+    /// The sweep was not confirmed by the user.
+    Aborted,
+}
+
+impl Error {
+    /// The process exit code for this error, distinct for each kind of error
+    /// and from the code 2 of the usage errors of the command line parser.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Mnemonic(_) => 3,
+            Error::Network(_) => 4,
+            Error::Client(_) => 5,
+            Error::DerivationPath(_) => 6,
+            Error::Descriptor(_) => 7,
+            Error::Wallet(_) => 8,
+            Error::Address(_) => 9,
+            Error::Sync(_) => 10,
+            Error::FeeEstimates(_) => 11,
+            Error::Transaction(_) => 12,
+            Error::Signing(_) => 13,
+            Error::Broadcast(_) => 14,
+            Error::Io(_) => 15,
+            Error::Aborted => 16,
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Mnemonic(e) => write!(f, "Invalid mnemonic: {}", e),
+            Error::Network(e) => write!(f, "Invalid network: {}", e),
+            Error::Client(e) => write!(f, "Invalid backend: {}", e),
+            Error::DerivationPath(e) => write!(f, "Invalid derivation path: {}", e),
+            Error::Descriptor(e) => write!(f, "Invalid descriptor: {}", e),
+            Error::Wallet(e) => write!(f, "Error creating wallet: {}", e),
+            Error::Address(e) => write!(f, "Invalid address: {}", e),
+            Error::Sync(e) => write!(f, "Error syncing wallet: {}", e),
+            Error::FeeEstimates(e) => write!(f, "Error getting fee estimates: {}", e),
+            Error::Transaction(e) => write!(f, "Error creating transaction: {}", e),
+            Error::Signing(e) => write!(f, "Error signing transaction: {}", e),
+            Error::Broadcast(e) => write!(f, "Error broadcasting transaction: {}", e),
+            Error::Io(e) => write!(f, "Error reading input: {}", e),
+            Error::Aborted => write!(f, "Sweep aborted"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
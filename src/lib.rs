@@ -1,4 +1,8 @@
 pub mod bip39;
 pub mod cli;
+pub mod error;
 pub mod network;
+pub mod store;
 pub mod wallet;
+
+pub use error::Error;
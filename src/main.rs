@@ -1,6 +1,10 @@
+use std::process::exit;
 use sweepr::cli::run;
 
 #[tokio::main]
 async fn main() {
-    run().await;
+    if let Err(e) = run().await {
+        eprintln!("{}", e);
+        exit(e.exit_code());
+    }
 }
@@ -1,21 +1,255 @@
 use bdk::bitcoin::network::constants::Network;
+use bdk_electrum::electrum_client::{
+    Client as ElectrumClient, ConfigBuilder as ElectrumConfigBuilder, Socks5Config,
+};
 use bdk_esplora::esplora_client::{AsyncClient, Builder};
+use bitcoincore_rpc::{
+    jsonrpc::{simple_http::SimpleHttpTransport, Client as JsonRpcClient},
+    Auth, Client as RpcClient,
+};
+use nakamoto::{
+    client::{Client as CbfClient, Config as CbfConfig, Handle},
+    common::network::Network as CbfNetwork,
+    net::poll::{Reactor, Waker},
+};
+use std::{
+    net::{IpAddr, TcpStream, ToSocketAddrs},
+    path::Path,
+    thread,
+    time::Duration,
+};
 
-/// Creates a client from a url.
-pub fn create_client(url: &str) -> AsyncClient {
-    match Builder::new(url).build_async() {
+use crate::Error;
+
+/// How long the BIP157/158 light client waits for the peer before giving up,
+/// so that a peer that disconnects or stops answering does not hang the sync.
+pub const CBF_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// The handle of a BIP157/158 light client running in the background.
+pub type CbfHandle = Handle<Waker>;
+
+/// The chain backend used to sync the wallets, get fee estimates and broadcast transactions.
+pub enum Backend {
+    /// An Esplora server over HTTP(S).
+    Esplora(AsyncClient),
+    /// An Electrum server over TCP or SSL.
+    Electrum(ElectrumClient),
+    /// A Bitcoin Core node over RPC, without the need for an indexer.
+    Rpc(RpcClient),
+    /// A BIP157/158 light client that matches compact block filters locally,
+    /// so the peer never learns which scripts belong to the wallet.
+    Cbf(CbfHandle),
+}
+
+impl Backend {
+    /// Whether the wallets synced with the backend can be persisted between runs.
+    /// The rpc backend only finds the coins that are still unspent and never sees them being spent,
+    /// so its wallets are kept in memory and scanned from scratch every run.
+    pub fn persists_wallets(&self) -> bool {
+        !matches!(self, Backend::Rpc(_))
+    }
+}
+
+/// Creates a backend from its kind (esplora, electrum, rpc or cbf) and the url of its server.
+/// The rpc backend also needs the credentials of the node,
+/// and the cbf backend stores the block headers in the data directory.
+/// Every backend but cbf can connect through a SOCKS5 proxy, which `.onion` urls require.
+pub fn create_backend(
+    backend: &str,
+    url: &str,
+    network: Network,
+    rpc_auth: Option<Auth>,
+    data_dir: Option<&Path>,
+    proxy: Option<&str>,
+) -> Result<Backend, Error> {
+    match proxy {
+        Some(proxy) => {
+            proxy_address(proxy)?;
+        }
+        None if is_onion(url) => {
+            return Err(Error::Client(format!(
+                "{} is an onion service, it needs a proxy such as socks5h://127.0.0.1:9050",
+                url
+            )))
+        }
+        None => {}
+    }
+    match (backend.to_lowercase().as_str(), rpc_auth, data_dir) {
+        ("esplora", _, _) => Ok(Backend::Esplora(create_client(url, proxy)?)),
+        ("electrum", _, _) => Ok(Backend::Electrum(create_electrum_client(url, proxy)?)),
+        ("rpc", Some(auth), _) => Ok(Backend::Rpc(create_rpc_client(url, auth, proxy)?)),
+        ("rpc", None, _) => Err(Error::Client(
+            "the rpc backend requires a cookie file or a user and password".to_string(),
+        )),
+        ("cbf", _, _) if proxy.is_some() => Err(Error::Client(
+            "the cbf backend cannot connect through a proxy".to_string(),
+        )),
+        ("cbf", _, Some(data_dir)) => Ok(Backend::Cbf(create_cbf_client(url, network, data_dir)?)),
+        ("cbf", _, None) => Err(Error::Client(
+            "the cbf backend stores block headers in the data directory, it cannot be ephemeral"
+                .to_string(),
+        )),
+        _ => Err(Error::Client(format!("unknown backend {}", backend))),
+    }
+}
+
+/// Checks that every connection goes through a proxy that resolves host names itself (`socks5h`),
+/// such as Tor, so that neither the addresses nor the DNS requests leak.
+pub fn require_tor(proxy: Option<&str>) -> Result<(), Error> {
+    match proxy {
+        Some(proxy) if proxy.starts_with("socks5h://") => Ok(()),
+        Some(proxy) => Err(Error::Client(format!(
+            "{} resolves host names locally, use a socks5h:// proxy",
+            proxy
+        ))),
+        None => Err(Error::Client(
+            "refusing to connect without a proxy".to_string(),
+        )),
+    }
+}
+
+/// Creates an Esplora client from a url, optionally through a SOCKS5 proxy.
+pub fn create_client(url: &str, proxy: Option<&str>) -> Result<AsyncClient, Error> {
+    let builder = match proxy {
+        Some(proxy) => Builder::new(url).proxy(proxy),
+        None => Builder::new(url),
+    };
+    match builder.build_async() {
+        Ok(client) => Ok(client),
+        Err(e) => Err(Error::Client(e.to_string())),
+    }
+}
+
+/// Creates an Electrum client from a url, such as `ssl://host:50002` or `tcp://host:50001`,
+/// optionally through a SOCKS5 proxy.
+/// The connection to the server is established right away.
+pub fn create_electrum_client(url: &str, proxy: Option<&str>) -> Result<ElectrumClient, Error> {
+    let socks5 = match proxy {
+        Some(proxy) => Some(Socks5Config::new(proxy_address(proxy)?)),
+        None => None,
+    };
+    let config = match ElectrumConfigBuilder::new().socks5(socks5) {
+        Ok(builder) => builder.build(),
+        Err(e) => return Err(Error::Client(e.to_string())),
+    };
+    match ElectrumClient::from_config(url, config) {
+        Ok(client) => Ok(client),
+        Err(e) => Err(Error::Client(e.to_string())),
+    }
+}
+
+/// Creates a Bitcoin Core RPC client from a url, such as `http://localhost:8332`,
+/// authenticated with a cookie file or a user and password, optionally through a SOCKS5 proxy.
+/// The RPC transport resolves host names locally before handing the address to the proxy,
+/// so through a proxy the url must contain the IP address of the node, and `.onion` urls are refused.
+pub fn create_rpc_client(url: &str, auth: Auth, proxy: Option<&str>) -> Result<RpcClient, Error> {
+    let proxy = match proxy {
+        Some(proxy) => proxy_address(proxy)?,
+        None => return RpcClient::new(url, auth).map_err(|e| Error::Client(e.to_string())),
+    };
+    let host = url_host(url);
+    if host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+        .is_err()
+    {
+        return Err(Error::Client(format!(
+            "the rpc backend would resolve {} outside of the proxy, use the IP address of the node",
+            host
+        )));
+    }
+    let (user, password) = match auth.get_user_pass() {
+        Ok((Some(user), password)) => (user, password),
+        Ok((None, _)) => (String::new(), None),
+        Err(e) => return Err(Error::Client(e.to_string())),
+    };
+    let transport = SimpleHttpTransport::builder()
+        .url(url)
+        .and_then(|builder| builder.auth(user, password).proxy_addr(proxy));
+    match transport {
+        Ok(builder) => Ok(RpcClient::from_jsonrpc(JsonRpcClient::with_transport(
+            builder.build(),
+        ))),
+        Err(e) => Err(Error::Client(e.to_string())),
+    }
+}
+
+/// Starts a BIP157/158 light client in the background connected to a peer, such as `localhost:8333`,
+/// which must serve compact block filters (`peerblockfilters=1` in Bitcoin Core).
+/// The block headers and filter headers are stored in the data directory.
+/// Waiting for the peer times out after [`CBF_TIMEOUT`].
+pub fn create_cbf_client(
+    peer: &str,
+    network: Network,
+    data_dir: &Path,
+) -> Result<CbfHandle, Error> {
+    let peer = match peer.to_socket_addrs().map(|mut addrs| addrs.next()) {
+        Ok(Some(peer)) => peer,
+        Ok(None) => return Err(Error::Client(format!("no address for peer {}", peer))),
+        Err(e) => return Err(Error::Client(e.to_string())),
+    };
+    let network = match network {
+        Network::Bitcoin => CbfNetwork::Mainnet,
+        Network::Testnet => CbfNetwork::Testnet,
+        Network::Signet => CbfNetwork::Signet,
+        Network::Regtest => CbfNetwork::Regtest,
+    };
+    let config = CbfConfig {
+        network,
+        connect: vec![peer],
+        root: data_dir.to_path_buf(),
+        ..CbfConfig::default()
+    };
+    let client = match CbfClient::<Reactor<TcpStream>>::new() {
         Ok(client) => client,
-        Err(e) => panic!("Invalid esplora url: {}", e),
+        Err(e) => return Err(Error::Client(e.to_string())),
+    };
+    let mut handle = client.handle();
+    handle.set_timeout(CBF_TIMEOUT);
+    thread::spawn(move || client.run(config));
+    Ok(handle)
+}
+
+/// The address of a SOCKS5 proxy, such as `127.0.0.1:9050` for `socks5h://127.0.0.1:9050`.
+fn proxy_address(proxy: &str) -> Result<&str, Error> {
+    match proxy
+        .strip_prefix("socks5h://")
+        .or_else(|| proxy.strip_prefix("socks5://"))
+    {
+        Some(address) => Ok(address),
+        None => Err(Error::Client(format!(
+            "unsupported proxy {}, use socks5h://host:port",
+            proxy
+        ))),
+    }
+}
+
+/// The host of a url, such as `127.0.0.1` for `http://127.0.0.1:8332` or `[::1]` for `http://[::1]:8332`.
+fn url_host(url: &str) -> &str {
+    let authority = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let authority = authority.split('/').next().unwrap_or_default();
+    let authority = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, host)| host);
+    match authority.find(']') {
+        Some(end) if authority.starts_with('[') => &authority[..=end],
+        _ => authority.split(':').next().unwrap_or_default(),
     }
 }
 
+/// Checks if the host of a url is an onion service.
+fn is_onion(url: &str) -> bool {
+    url_host(url).ends_with(".onion")
+}
+
 /// Creates a network from a string.
-pub fn create_network(network: &str) -> Network {
-    let network = network.to_lowercase();
-    match network.as_str() {
-        "mainnet" => Network::Bitcoin,
-        "testnet" => Network::Testnet,
-        "regtest" => Network::Regtest,
-        _ => panic!("Invalid network"),
+pub fn create_network(network: &str) -> Result<Network, Error> {
+    match network.to_lowercase().as_str() {
+        "mainnet" => Ok(Network::Bitcoin),
+        "testnet" => Ok(Network::Testnet),
+        "signet" => Ok(Network::Signet),
+        "regtest" => Ok(Network::Regtest),
+        _ => Err(Error::Network(network.to_string())),
     }
 }
@@ -0,0 +1,150 @@
+use bdk::{
+    bitcoin::{network::constants::Network, Txid},
+    descriptor::{calc_checksum, ExtendedDescriptor},
+    wallet::ChangeSet,
+};
+use bdk_chain::{Append, PersistBackend};
+use bdk_file_store::{IterError, Store};
+use std::{
+    convert::Infallible,
+    env::temp_dir,
+    fs::{create_dir_all, read_to_string, write},
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::Error;
+
+/// The magic string used to identify the database.
+const DB_MAGIC: &str = "sweepr";
+
+/// A store that keeps the changes of a wallet in memory and never writes them to disk.
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    changeset: ChangeSet,
+}
+
+impl PersistBackend<ChangeSet> for MemoryStore {
+    type WriteError = Infallible;
+    type LoadError = Infallible;
+
+    fn write_changes(&mut self, changeset: &ChangeSet) -> Result<(), Self::WriteError> {
+        self.changeset.append(changeset.clone());
+        Ok(())
+    }
+
+    fn load_from_persistence(&mut self) -> Result<ChangeSet, Self::LoadError> {
+        Ok(self.changeset.clone())
+    }
+}
+
+/// The store of a wallet, either a file in the data directory or memory only.
+pub enum WalletStore<'a> {
+    /// The wallet is persisted in a file.
+    File(Store<'a, ChangeSet>),
+    /// The wallet is kept in memory, leaving no traces on disk.
+    Memory(MemoryStore),
+}
+
+impl PersistBackend<ChangeSet> for WalletStore<'_> {
+    type WriteError = io::Error;
+    type LoadError = IterError;
+
+    fn write_changes(&mut self, changeset: &ChangeSet) -> Result<(), Self::WriteError> {
+        match self {
+            WalletStore::File(store) => store.write_changes(changeset),
+            WalletStore::Memory(store) => match store.write_changes(changeset) {
+                Ok(()) => Ok(()),
+                Err(e) => match e {},
+            },
+        }
+    }
+
+    fn load_from_persistence(&mut self) -> Result<ChangeSet, Self::LoadError> {
+        match self {
+            WalletStore::File(store) => store.load_from_persistence(),
+            WalletStore::Memory(store) => match store.load_from_persistence() {
+                Ok(changeset) => Ok(changeset),
+                Err(e) => match e {},
+            },
+        }
+    }
+}
+
+/// The default data directory, the user cache directory or the system temp directory as a fallback.
+pub fn default_data_dir() -> PathBuf {
+    dirs::cache_dir().unwrap_or_else(temp_dir).join("sweepr")
+}
+
+/// The path of the store of a wallet in the data directory,
+/// keyed by the network and the checksum of the wallet's external descriptor.
+pub fn store_path(
+    data_dir: &Path,
+    network: Network,
+    descriptor: &ExtendedDescriptor,
+) -> Result<PathBuf, Error> {
+    match calc_checksum(&descriptor.to_string()) {
+        Ok(checksum) => Ok(data_dir.join(format!("{}-{}.db", network, checksum))),
+        Err(e) => Err(Error::Descriptor(e.to_string())),
+    }
+}
+
+/// Opens the store of a wallet in the data directory, creating the directory if needed.
+/// Without a data directory the store is kept in memory and nothing is written to disk.
+pub fn open_store<'a>(
+    data_dir: Option<&Path>,
+    network: Network,
+    descriptor: &ExtendedDescriptor,
+) -> Result<WalletStore<'a>, Error> {
+    let data_dir = match data_dir {
+        Some(data_dir) => data_dir,
+        None => return Ok(WalletStore::Memory(MemoryStore::default())),
+    };
+    if let Err(e) = create_dir_all(data_dir) {
+        return Err(Error::Wallet(e.to_string()));
+    }
+    let db_path = store_path(data_dir, network, descriptor)?;
+    match Store::<ChangeSet>::new_from_path(DB_MAGIC.as_bytes(), db_path) {
+        Ok(db) => Ok(WalletStore::File(db)),
+        Err(e) => Err(Error::Wallet(e.to_string())),
+    }
+}
+
+/// The path of the file recording the txids of the last sweep of a network in the data directory.
+pub fn last_sweep_path(data_dir: &Path, network: Network) -> PathBuf {
+    data_dir.join(format!("{}-last-sweep.txt", network))
+}
+
+/// Records the txids of the last sweep in the data directory, one per line,
+/// so that it can be bumped without giving its txid.
+pub fn write_last_sweep(data_dir: &Path, network: Network, txids: &[Txid]) -> Result<(), Error> {
+    if let Err(e) = create_dir_all(data_dir) {
+        return Err(Error::Wallet(e.to_string()));
+    }
+    let txids: Vec<String> = txids.iter().map(ToString::to_string).collect();
+    match write(last_sweep_path(data_dir, network), txids.join("\n")) {
+        Ok(()) => Ok(()),
+        Err(e) => Err(Error::Wallet(e.to_string())),
+    }
+}
+
+/// Reads the txids of the last sweep recorded in the data directory.
+pub fn read_last_sweep(data_dir: &Path, network: Network) -> Result<Vec<Txid>, Error> {
+    let path = last_sweep_path(data_dir, network);
+    let txids = match read_to_string(&path) {
+        Ok(txids) => txids,
+        Err(e) => {
+            return Err(Error::Wallet(format!(
+                "no sweep recorded in {}: {}",
+                path.display(),
+                e
+            )))
+        }
+    };
+    txids
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Txid::from_str(line.trim()).map_err(|e| Error::Wallet(e.to_string())))
+        .collect()
+}
@@ -1,29 +1,77 @@
 use bdk::{
     bitcoin::{
-        network::constants::Network,
-        psbt::PartiallySignedTransaction,
+        network::constants::{Network, ServiceFlags},
+        psbt::{self, PartiallySignedTransaction},
         secp256k1::Secp256k1,
-        util::{address::Address, bip32::DerivationPath},
+        util::{
+            address::{Address, AddressType},
+            bip32::{ChildNumber, DerivationPath, ExtendedPubKey},
+        },
+        BlockHash, OutPoint, Script, Transaction, Txid,
     },
     descriptor,
-    descriptor::IntoWalletDescriptor,
-    keys::bip39::Mnemonic,
-    wallet::{ChangeSet, Wallet},
-    FeeRate, SignOptions,
+    descriptor::{template::DescriptorTemplateOut, IntoWalletDescriptor},
+    keys::bip39::MnemonicWithPassphrase,
+    wallet::{
+        coin_selection::CoinSelectionAlgorithm,
+        tx_builder::{TxBuilder, TxBuilderContext, TxOrdering},
+        AddressIndex, Wallet,
+    },
+    FeeRate, KeychainKind, LocalUtxo, SignOptions, TransactionDetails,
+};
+use bdk_chain::{keychain::LocalUpdate, BlockId, ConfirmationTime, ConfirmationTimeAnchor};
+use bdk_electrum::{electrum_client::ElectrumApi, ElectrumExt};
+use bdk_esplora::EsploraAsyncExt;
+use bitcoincore_rpc::{
+    json::{EstimateSmartFeeResult, ScanTxOutRequest},
+    Client as RpcClient, RpcApi,
 };
-use bdk_esplora::{esplora_client::AsyncClient, EsploraAsyncExt};
-use bdk_file_store::Store;
+use futures::future::join_all;
+use nakamoto::client::{handle::Handle as _, Event};
 use std::str::FromStr;
-use std::{collections::HashMap, env::temp_dir};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fmt,
+    path::Path,
+};
+use tokio::task;
+
+use crate::{
+    network::{Backend, CbfHandle, CBF_TIMEOUT},
+    store::{open_store, WalletStore},
+    Error,
+};
+
+/// The minimum fee rate in sat/vB relayed by nodes with the default policy.
+pub const MIN_RELAY_FEE_RATE: f64 = 1.0;
+
+/// The number of blocks after which coinbase outputs can be spent.
+const COINBASE_MATURITY: u32 = 100;
+
+/// The weight of an input without its script signature and witness:
+/// the outpoint, the sequence and the length of the script signature.
+const TXIN_BASE_WEIGHT: usize = (32 + 4 + 4 + 1) * 4;
+/// The weight of a transaction without its inputs and outputs:
+/// the version, the lock time, the input and output counts, and the segwit marker and flag.
+const TX_BASE_WEIGHT: usize = (4 + 4 + 1 + 1) * 4 + 2;
+/// The weight of an output to the largest common script, pay to witness script hash or taproot.
+const TXOUT_WEIGHT: usize = (8 + 1 + 34) * 4;
+/// The largest common dust limit in sats, that of pay to public key hash outputs,
+/// below which the remainder of a sweep would not be relayed.
+const DUST_LIMIT: u64 = 546;
 
-/// The magic string used to identify the database.
-const DB_MAGIC: &str = "sweepr";
 /// The number of unused addresses to check before stopping.
 const STOP_GAP: usize = 5;
 /// The number of parallel requests to send to the esplora server.
 const PARALLEL_REQUESTS: usize = 5;
+/// The number of scripts to request in a single batch from the electrum server.
+const BATCH_SIZE: usize = 5;
+/// The number of scripts of each keychain scanned by the backends that cannot stop at a gap,
+/// the UTXO set of the bitcoin core node and the compact block filters.
+const SCAN_RANGE: u32 = 1_000;
 
 /// Derivation paths for different wallets without the last index.
+/// The coin type `0'` is replaced by the coin type of the network when deriving the wallets.
 pub const DERIVATION_PATHS: &[&str; 11] = &[
     "m/44'/0'/0'/",
     "m/48'/0'/0'/",
@@ -38,145 +86,1733 @@ pub const DERIVATION_PATHS: &[&str; 11] = &[
     "m/86'/0'/0'/",
 ];
 
-/// Derivation paths for different wallets with the last index.
-pub fn create_derivation_paths_with_last_index(input: &str) -> (DerivationPath, DerivationPath) {
+/// Gets the BIP44 coin type of a network, `0'` for mainnet and `1'` for testnet, signet and regtest.
+pub fn get_coin_type(network: Network) -> u32 {
+    match network {
+        Network::Bitcoin => 0,
+        _ => 1,
+    }
+}
+
+/// Derivation paths for different wallets with the last index and the given coin type.
+pub fn create_derivation_paths_with_last_index(
+    input: &str,
+    coin_type: u32,
+) -> Result<(DerivationPath, DerivationPath), Error> {
     let extenal_str = input.to_owned() + "0";
     let internal_str = input.to_owned() + "1";
-    (
-        create_derivation_path(extenal_str.as_str()),
-        create_derivation_path(internal_str.as_str()),
-    )
+    Ok((
+        set_hardened_child(create_derivation_path(extenal_str.as_str())?, 1, coin_type)?,
+        set_hardened_child(create_derivation_path(internal_str.as_str())?, 1, coin_type)?,
+    ))
+}
+
+/// Gets the account (third level) of a derivation path.
+pub fn get_account(derivation_path: &DerivationPath) -> Option<u32> {
+    match derivation_path.into_iter().nth(2) {
+        Some(ChildNumber::Hardened { index }) => Some(*index),
+        _ => None,
+    }
+}
+
+/// Replaces the account (third level) of a derivation path.
+pub fn with_account(
+    derivation_path: &DerivationPath,
+    account: u32,
+) -> Result<DerivationPath, Error> {
+    set_hardened_child(derivation_path.clone(), 2, account)
+}
+
+/// Replaces the child at the given depth of a derivation path with a hardened index.
+fn set_hardened_child(
+    derivation_path: DerivationPath,
+    depth: usize,
+    index: u32,
+) -> Result<DerivationPath, Error> {
+    let mut children: Vec<ChildNumber> = derivation_path.into_iter().copied().collect();
+    let child = match ChildNumber::from_hardened_idx(index) {
+        Ok(child) => child,
+        Err(e) => return Err(Error::DerivationPath(e.to_string())),
+    };
+    match children.get_mut(depth) {
+        Some(old_child) => *old_child = child,
+        None => {
+            return Err(Error::DerivationPath(format!(
+                "{} has no child at depth {}",
+                derivation_path, depth
+            )))
+        }
+    }
+    Ok(DerivationPath::from(children))
 }
 
 /// Creates a derivation path from a string.
-pub fn create_derivation_path(input: &str) -> DerivationPath {
+pub fn create_derivation_path(input: &str) -> Result<DerivationPath, Error> {
     match DerivationPath::from_str(input) {
-        Ok(derivation_path) => derivation_path,
-        Err(e) => panic!("Invalid derivation path: {}", e),
+        Ok(derivation_path) => Ok(derivation_path),
+        Err(e) => Err(Error::DerivationPath(e.to_string())),
+    }
+}
+
+/// Script types that a wallet can be derived with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    /// Pay to public key hash (`pkh`), legacy `1...` addresses.
+    Legacy,
+    /// Pay to witness public key hash nested in pay to script hash (`sh(wpkh)`), `3...` addresses.
+    NestedSegwit,
+    /// Pay to witness public key hash (`wpkh`), `bc1q...` addresses.
+    Segwit,
+    /// Pay to taproot (`tr`), `bc1p...` addresses.
+    Taproot,
+}
+
+/// Gets the script type from the purpose of a derivation path.
+/// BIP44 and BIP47 are legacy, BIP49 is nested segwit, BIP84 is segwit and BIP86 is taproot.
+/// Any other purpose defaults to segwit.
+pub fn get_script_type(derivation_path: &DerivationPath) -> ScriptType {
+    match derivation_path.into_iter().next() {
+        Some(ChildNumber::Hardened { index: 44 }) | Some(ChildNumber::Hardened { index: 47 }) => {
+            ScriptType::Legacy
+        }
+        Some(ChildNumber::Hardened { index: 49 }) => ScriptType::NestedSegwit,
+        Some(ChildNumber::Hardened { index: 86 }) => ScriptType::Taproot,
+        _ => ScriptType::Segwit,
+    }
+}
+
+/// Creates a descriptor from a mnemonic with an optional BIP39 passphrase and a derivation path.
+/// The script type is derived from the purpose of the derivation path.
+pub fn create_descriptor(
+    seed: MnemonicWithPassphrase,
+    derivation_path: DerivationPath,
+) -> Result<DescriptorTemplateOut, Error> {
+    let script_type = get_script_type(&derivation_path);
+    let key = (seed, derivation_path);
+    let descriptor = match script_type {
+        ScriptType::Legacy => descriptor!(pkh(key)),
+        ScriptType::NestedSegwit => descriptor!(sh(wpkh(key))),
+        ScriptType::Segwit => descriptor!(wpkh(key)),
+        ScriptType::Taproot => descriptor!(tr(key)),
+    };
+    match descriptor {
+        Ok(descriptor) => Ok(descriptor),
+        Err(e) => Err(Error::Descriptor(e.to_string())),
     }
 }
 
-/// Creates a wallet from a mnemonic, a network type, and an internal and external derivation paths.
+/// Creates a wallet from a mnemonic with an optional BIP39 passphrase, a network type,
+/// and an internal and external derivation paths.
+/// The wallet is persisted in its own store in the data directory,
+/// or only kept in memory if no data directory is given.
 pub fn create_wallet<'a>(
-    seed: Mnemonic,
+    seed: MnemonicWithPassphrase,
     network: Network,
     derivation_path_external: DerivationPath,
     derivation_path_internal: DerivationPath,
-) -> Wallet<Store<'a, ChangeSet>> {
-    let db_path = temp_dir().join("sweepr");
-    let db = Store::<ChangeSet>::new_from_path(DB_MAGIC.as_bytes(), db_path).unwrap();
+    data_dir: Option<&Path>,
+) -> Result<Wallet<WalletStore<'a>>, Error> {
     let secp = Secp256k1::new();
 
-    // generate external and internal descriptor from mnemonic
-    let (external_descriptor, _ext_keymap) =
-        match descriptor!(wpkh((seed.clone(), derivation_path_external.clone())))
-            .unwrap()
-            .into_wallet_descriptor(&secp, network)
-        {
-            Ok((extended_descriptor, keymap)) => (extended_descriptor, keymap),
-            Err(e) => panic!("Invalid external derivation path: {}", e),
-        };
-    let (internal_descriptor, _int_keymap) =
-        match descriptor!(wpkh((seed.clone(), derivation_path_internal.clone())))
-            .unwrap()
-            .into_wallet_descriptor(&secp, network)
-        {
-            Ok((extended_descriptor, keymap)) => (extended_descriptor, keymap),
-            Err(e) => panic!("Invalid internal derivation path: {}", e),
-        };
+    // generate external and internal descriptor from mnemonic and passphrase
+    let external_descriptor = match create_descriptor(seed.clone(), derivation_path_external)?
+        .into_wallet_descriptor(&secp, network)
+    {
+        Ok(descriptor) => descriptor,
+        Err(e) => return Err(Error::Descriptor(format!("external: {}", e))),
+    };
+    let internal_descriptor = match create_descriptor(seed, derivation_path_internal)?
+        .into_wallet_descriptor(&secp, network)
+    {
+        Ok(descriptor) => descriptor,
+        Err(e) => return Err(Error::Descriptor(format!("internal: {}", e))),
+    };
+    let db = open_store(data_dir, network, &external_descriptor.0)?;
 
-    Wallet::new(external_descriptor, Some(internal_descriptor), db, network).unwrap()
+    match Wallet::new(external_descriptor, Some(internal_descriptor), db, network) {
+        Ok(wallet) => Ok(wallet),
+        Err(e) => Err(Error::Wallet(format!("{:?}", e))),
+    }
 }
 
-/// Creates an address from a string.
-pub fn create_address(input: &str) -> Address {
+/// Creates a wallet from the descriptor of a destination, such as `wpkh(xprv.../84'/0'/0'/0/*)`,
+/// or from the extended key of an account, whose receive and change addresses are derived as native segwit.
+/// The wallet can only sign with private keys, and is watch-only with public keys.
+/// The wallet is persisted in its own store in the data directory,
+/// or only kept in memory if no data directory is given.
+pub fn create_destination_wallet<'a>(
+    descriptor: &str,
+    network: Network,
+    data_dir: Option<&Path>,
+) -> Result<Wallet<WalletStore<'a>>, Error> {
+    let secp = Secp256k1::new();
+
+    let descriptor = descriptor.trim();
+    let (external, internal) = if descriptor.contains('(') {
+        (descriptor.to_string(), None)
+    } else {
+        (
+            format!("wpkh({}/0/*)", descriptor),
+            Some(format!("wpkh({}/1/*)", descriptor)),
+        )
+    };
+    let external_descriptor = match external.as_str().into_wallet_descriptor(&secp, network) {
+        Ok(descriptor) => descriptor,
+        Err(e) => return Err(Error::Descriptor(format!("external: {}", e))),
+    };
+    let internal_descriptor = match internal
+        .map(|internal| internal.as_str().into_wallet_descriptor(&secp, network))
+        .transpose()
+    {
+        Ok(descriptor) => descriptor,
+        Err(e) => return Err(Error::Descriptor(format!("internal: {}", e))),
+    };
+    let db = open_store(data_dir, network, &external_descriptor.0)?;
+
+    match Wallet::new(external_descriptor, internal_descriptor, db, network) {
+        Ok(wallet) => Ok(wallet),
+        Err(e) => Err(Error::Wallet(format!("{:?}", e))),
+    }
+}
+
+/// Creates an address from a string, checking that it belongs to the network.
+/// Testnet addresses are valid on signet and regtest too, except for regtest bech32 addresses.
+pub fn create_address(input: &str, network: Network) -> Result<Address, Error> {
     match Address::from_str(input) {
-        Ok(address) => address,
-        Err(e) => panic!("Invalid address: {}", e),
+        Ok(address) if address.is_valid_for_network(network) => Ok(address),
+        Ok(address) => Err(Error::Address(format!(
+            "{} is a {} address, not a {} one",
+            input, address.network, network
+        ))),
+        Err(e) => Err(Error::Address(e.to_string())),
     }
 }
 
-/// Create a Signed Transaction from a wallet using all available coins to send to a given address.
-/// Estimate the fee using the Esplora client.
-/// Tries to use fee rate such that it will be included in the next block.
+/// Gets a warning for addresses whose script type is legacy or uncommon,
+/// which cost more to spend from or may not be supported by the wallet receiving the sweep.
+pub fn address_warning(address: &Address) -> Option<String> {
+    match address.address_type() {
+        Some(AddressType::P2wpkh) | Some(AddressType::P2wsh) | Some(AddressType::P2tr) => None,
+        Some(AddressType::P2pkh) => Some(format!(
+            "{} is a legacy address, spending the swept coins from it costs more in fees",
+            address
+        )),
+        Some(AddressType::P2sh) => Some(format!(
+            "{} is a legacy script hash address, check that the receiving wallet can spend from it",
+            address
+        )),
+        _ => Some(format!(
+            "{} has an uncommon script type, check that the receiving wallet can spend from it",
+            address
+        )),
+    }
+}
+
+/// The share of a sweep sent to a destination.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Share {
+    /// A fixed amount in sats.
+    Amount(u64),
+    /// A percentage of the swept coins.
+    Percent(f64),
+}
+
+/// What a destination sweeps to.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Payee {
+    /// A single address.
+    Address(Address),
+    /// An output descriptor or extended public key,
+    /// from which a fresh unused address is derived for every output.
+    Descriptor(String),
+}
+
+impl fmt::Display for Payee {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Payee::Address(address) => write!(f, "{}", address),
+            Payee::Descriptor(descriptor) => write!(f, "{}", descriptor),
+        }
+    }
+}
+
+/// Creates a payee from a string, either an address, an output descriptor
+/// such as `wpkh([fp/84'/0'/0']xpub.../0/*)` or an extended public key.
+/// Both addresses and descriptors are checked against the network.
+pub fn create_payee(input: &str, network: Network) -> Result<Payee, Error> {
+    if input.contains('(') || ExtendedPubKey::from_str(input).is_ok() {
+        create_destination_wallet(input, network, None)?;
+        return Ok(Payee::Descriptor(input.to_string()));
+    }
+    Ok(Payee::Address(create_address(input, network)?))
+}
+
+/// A payee to sweep to with its share of the sweep.
+/// The last destination of a sweep receives the remainder and has no share.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Destination {
+    /// The address or descriptor to sweep to.
+    pub payee: Payee,
+    /// The share of the sweep, none for the remainder.
+    pub share: Option<Share>,
+}
+
+impl From<Payee> for Destination {
+    /// A destination receiving the remainder of the sweep.
+    fn from(payee: Payee) -> Self {
+        Destination { payee, share: None }
+    }
+}
+
+impl From<Address> for Destination {
+    /// A destination receiving the remainder of the sweep.
+    fn from(address: Address) -> Self {
+        Payee::Address(address).into()
+    }
+}
+
+/// Creates a destination from a string, either `payee`, `payee:amount` in sats
+/// or `payee:percent%`, where the payee is an address, an output descriptor or an extended public key.
+pub fn create_destination(input: &str, network: Network) -> Result<Destination, Error> {
+    let (address, share) = match input.rsplit_once(':') {
+        Some((address, share)) => (address, Some(share)),
+        None => (input, None),
+    };
+    let share = match share.map(|share| (share, share.strip_suffix('%'))) {
+        Some((_, Some(percent))) => match percent.parse::<f64>() {
+            Ok(percent) if percent > 0.0 && percent < 100.0 => Some(Share::Percent(percent)),
+            _ => {
+                return Err(Error::Address(format!(
+                    "invalid percentage in {}, it must be between 0 and 100",
+                    input
+                )))
+            }
+        },
+        Some((amount, None)) => match amount.parse::<u64>() {
+            Ok(amount) if amount > 0 => Some(Share::Amount(amount)),
+            _ => return Err(Error::Address(format!("invalid amount in {}", input))),
+        },
+        None => None,
+    };
+    Ok(Destination {
+        payee: create_payee(address, network)?,
+        share,
+    })
+}
+
+/// Checks that every destination but the last has a share,
+/// and that the percentages leave something for the last one, which receives the remainder.
+pub fn check_destinations(destinations: &[Destination]) -> Result<(), Error> {
+    let (remainder, others) = match destinations.split_last() {
+        Some(split) => split,
+        None => return Err(Error::Address("missing address".to_string())),
+    };
+    if remainder.share.is_some() {
+        return Err(Error::Address(format!(
+            "the last destination {} receives the remainder, it cannot have an amount",
+            remainder.payee
+        )));
+    }
+    if let Some(destination) = others
+        .iter()
+        .find(|destination| destination.share.is_none())
+    {
+        return Err(Error::Address(format!(
+            "missing amount for {}, only the last destination receives the remainder",
+            destination.payee
+        )));
+    }
+    let percent: f64 = others
+        .iter()
+        .filter_map(|destination| match destination.share {
+            Some(Share::Percent(percent)) => Some(percent),
+            _ => None,
+        })
+        .sum();
+    if percent >= 100.0 {
+        return Err(Error::Address(format!(
+            "the percentages add up to {}%, leaving nothing for the remainder",
+            percent
+        )));
+    }
+    Ok(())
+}
+
+/// Derives the destinations of every sweep, replacing each descriptor with a fresh unused address.
+/// The wallet of every descriptor is synced with the backend first, so that the addresses
+/// that already have history are skipped, and is shared by the destinations and sweeps
+/// using the same descriptor, so that no address is given to two outputs.
+pub async fn derive_destinations(
+    destinations: &[Destination],
+    sweeps: usize,
+    network: Network,
+    data_dir: Option<&Path>,
+    backend: &Backend,
+) -> Result<Vec<Vec<Destination>>, Error> {
+    check_destinations_backend(destinations, backend)?;
+    let data_dir = data_dir.filter(|_| backend.persists_wallets());
+    let mut wallets = HashMap::new();
+    for destination in destinations {
+        if let Payee::Descriptor(descriptor) = &destination.payee {
+            if !wallets.contains_key(descriptor) {
+                let mut wallet = create_destination_wallet(descriptor, network, data_dir)?;
+                sync_wallet(&mut wallet, backend).await?;
+                wallets.insert(descriptor.clone(), wallet);
+            }
+        }
+    }
+
+    let mut derived = Vec::with_capacity(sweeps);
+    for _ in 0..sweeps {
+        let mut sweep_destinations = Vec::with_capacity(destinations.len());
+        for destination in destinations {
+            let payee = match &destination.payee {
+                Payee::Descriptor(descriptor) => match wallets.get_mut(descriptor) {
+                    Some(wallet) => Payee::Address(wallet.get_address(AddressIndex::New).address),
+                    None => destination.payee.clone(),
+                },
+                Payee::Address(address) => Payee::Address(address.clone()),
+            };
+            sweep_destinations.push(Destination {
+                payee,
+                share: destination.share,
+            });
+        }
+        derived.push(sweep_destinations);
+    }
+    Ok(derived)
+}
+
+/// Checks that the backend can tell which addresses of the descriptor destinations were used.
+/// The rpc backend only sees the addresses that still hold coins,
+/// so an address whose coins were spent would look unused and be given again.
+pub fn check_destinations_backend(
+    destinations: &[Destination],
+    backend: &Backend,
+) -> Result<(), Error> {
+    let descriptor = destinations
+        .iter()
+        .find(|destination| matches!(destination.payee, Payee::Descriptor(_)));
+    match (descriptor, backend) {
+        (Some(destination), Backend::Rpc(_)) => Err(Error::Address(format!(
+            "the rpc backend cannot tell the used addresses of {}, use an address or another backend",
+            destination.payee
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Splits the total value in sats of the coins of a sweep across its destinations:
+/// the scripts and amounts of the destinations with a share,
+/// and the address of the last destination, which receives the remainder minus the fee.
+fn split_destinations(
+    destinations: &[Destination],
+    total: u64,
+) -> Result<(Vec<(Address, u64)>, Address), Error> {
+    check_destinations(destinations)?;
+    let (remainder, others) = match destinations.split_last() {
+        Some(split) => split,
+        None => return Err(Error::Address("missing address".to_string())),
+    };
+    let recipients: Vec<(Address, u64)> = others
+        .iter()
+        .map(|destination| {
+            let amount = match destination.share {
+                Some(Share::Amount(amount)) => amount,
+                Some(Share::Percent(percent)) => (total as f64 * percent / 100.0).floor() as u64,
+                None => 0,
+            };
+            Ok((payee_address(&destination.payee)?, amount))
+        })
+        .collect::<Result<_, Error>>()?;
+    let sent: u64 = recipients.iter().map(|(_, amount)| amount).sum();
+    if sent >= total {
+        return Err(Error::Transaction(format!(
+            "the destinations add up to {} sats, leaving nothing of the {} sats swept for the remainder",
+            sent, total
+        )));
+    }
+    Ok((recipients, payee_address(&remainder.payee)?))
+}
+
+/// The address of a payee, which must have been derived if it is a descriptor.
+fn payee_address(payee: &Payee) -> Result<Address, Error> {
+    match payee {
+        Payee::Address(address) => Ok(address.clone()),
+        Payee::Descriptor(descriptor) => Err(Error::Address(format!(
+            "no address derived from {}",
+            descriptor
+        ))),
+    }
+}
+
+/// A signed sweep transaction with the details needed to review it before broadcasting.
+#[derive(Debug, Clone)]
+pub struct Sweep {
+    /// The signed transaction.
+    pub psbt: PartiallySignedTransaction,
+    /// The details of the transaction as computed by the wallet that built it.
+    pub details: TransactionDetails,
+    /// The addresses that receive a share of the coins, with their amounts in sats.
+    pub recipients: Vec<(Address, u64)>,
+    /// The address the remainder of the coins is swept to.
+    pub address: Address,
+}
+
+impl Sweep {
+    /// The fee paid by the transaction in satoshis.
+    pub fn fee(&self) -> u64 {
+        self.details.fee.unwrap_or_default()
+    }
+
+    /// The total amount of the swept coins in satoshis, including the fee.
+    pub fn amount(&self) -> u64 {
+        let outputs: u64 = self
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| txout.value)
+            .sum();
+        outputs + self.fee()
+    }
+
+    /// The virtual size of the signed transaction in vbytes.
+    pub fn vsize(&self) -> usize {
+        self.psbt.clone().extract_tx().vsize()
+    }
+
+    /// The fee rate of the signed transaction in sat/vB.
+    pub fn fee_rate(&self) -> f64 {
+        self.fee() as f64 / self.vsize() as f64
+    }
+}
+
+impl fmt::Display for Sweep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Inputs:")?;
+        for input in &self.psbt.unsigned_tx.input {
+            writeln!(f, "  {}", input.previous_output)?;
+        }
+        writeln!(f, "Amount: {} sats", self.amount())?;
+        writeln!(f, "Fee: {} sats", self.fee())?;
+        writeln!(f, "Fee rate: {:.2} sat/vB", self.fee_rate())?;
+        writeln!(f, "Virtual size: {} vB", self.vsize())?;
+        if self.recipients.is_empty() {
+            return write!(f, "Destination: {}", self.address);
+        }
+        writeln!(f, "Destinations:")?;
+        for (address, amount) in &self.recipients {
+            writeln!(f, "  {}: {} sats", address, amount)?;
+        }
+        write!(f, "  {}: remainder", self.address)
+    }
+}
+
+/// How the fee of a sweep is chosen.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeeStrategy {
+    /// A fee rate in sat/vB.
+    Rate(f64),
+    /// The fee rate estimated by the backend to confirm within a number of blocks.
+    Target(u64),
+    /// An absolute fee in sats.
+    Absolute(u64),
+}
+
+impl FeeStrategy {
+    /// The fee strategy with the confirmation target replaced by the fee rate estimated by the backend,
+    /// so that it is estimated once for every use.
+    pub async fn resolve(self, backend: &Backend) -> Result<FeeStrategy, Error> {
+        match self {
+            FeeStrategy::Target(blocks) => Ok(FeeStrategy::Rate(
+                get_fee_estimates(backend, Some(blocks)).await?,
+            )),
+            fee => Ok(fee),
+        }
+    }
+
+    /// The fee rate in sat/vB, if the fee strategy is a fee rate.
+    pub fn fee_rate(self) -> Option<f64> {
+        match self {
+            FeeStrategy::Rate(fee_rate) => Some(fee_rate),
+            _ => None,
+        }
+    }
+}
+
+/// Set the fee of a transaction being built from a fee strategy,
+/// whose confirmation target must have been resolved into a fee rate.
+fn set_fee<D, Cs: CoinSelectionAlgorithm, Ctx: TxBuilderContext>(
+    tx_builder: &mut TxBuilder<'_, D, Cs, Ctx>,
+    fee: FeeStrategy,
+) -> Result<(), Error> {
+    match fee {
+        FeeStrategy::Rate(fee_rate) => {
+            tx_builder.fee_rate(FeeRate::from_sat_per_vb(fee_rate as f32));
+        }
+        FeeStrategy::Absolute(fee) => {
+            tx_builder.fee_absolute(fee);
+        }
+        FeeStrategy::Target(blocks) => {
+            return Err(Error::Transaction(format!(
+                "no fee rate estimated for the target of {} blocks",
+                blocks
+            )))
+        }
+    }
+    Ok(())
+}
+
+impl Default for FeeStrategy {
+    /// The fee rate estimated to confirm in the next block.
+    fn default() -> Self {
+        FeeStrategy::Target(1)
+    }
+}
+
+/// Create a Signed Transaction from a wallet using all available coins to send to the given destinations,
+/// the last one receiving the remainder.
+/// Unconfirmed coins are only spent if they are included, and immature coinbase outputs never are.
+/// Coins that cost more to spend than they are worth at the fee rate are left behind unless dust is included.
+/// The fee is chosen with the fee strategy, estimating the fee rate with the backend if needed,
+/// and the transaction is refused if its fee rate is above the maximum fee rate in sat/vB.
 /// By default, the transaction is marked as RBF.
 pub async fn create_signed_transaction(
-    wallet: &mut Wallet<Store<'_, ChangeSet>>,
-    address: Address,
-    client: &AsyncClient,
-) -> PartiallySignedTransaction {
-    let fee_rate = get_fee_estimates(client, None).await;
+    wallet: &mut Wallet<WalletStore<'_>>,
+    destinations: &[Destination],
+    backend: &Backend,
+    fee: FeeStrategy,
+    max_fee_rate: f64,
+    include_unconfirmed: bool,
+    include_dust: bool,
+) -> Result<Sweep, Error> {
+    let fee = fee.resolve(backend).await?;
+    let dust_fee_rate = if include_dust { None } else { fee.fee_rate() };
+    let total = sweepable_coins(wallet, include_unconfirmed, dust_fee_rate)
+        .iter()
+        .map(|utxo| utxo.txout.value)
+        .sum();
+    let (recipients, address) = split_destinations(destinations, total)?;
+    let (mut psbt, details) = build_sweep(
+        wallet,
+        &recipients,
+        &address,
+        fee,
+        include_unconfirmed,
+        dust_fee_rate,
+        Vec::new(),
+    )?;
+    if let Err(e) = wallet.sign(&mut psbt, SignOptions::default()) {
+        return Err(Error::Signing(e.to_string()));
+    }
+    let sweep = Sweep {
+        psbt,
+        details,
+        recipients,
+        address,
+    };
+    check_max_fee_rate(&sweep, max_fee_rate)?;
+    Ok(sweep)
+}
+
+/// Create a single Signed Transaction from several wallets using all available coins to send to the given destinations,
+/// the last one receiving the remainder.
+/// The first wallet drains its own coins, while the coins of the remaining wallets are added as foreign UTXOs.
+/// Every wallet then signs the inputs it owns.
+/// Unconfirmed coins are only spent if they are included, and immature coinbase outputs never are.
+/// Coins that cost more to spend than they are worth at the fee rate are left behind unless dust is included.
+/// The fee is chosen with the fee strategy, estimating the fee rate with the backend if needed,
+/// and the transaction is refused if its fee rate is above the maximum fee rate in sat/vB.
+/// By default, the transaction is marked as RBF.
+pub async fn create_consolidated_transaction(
+    wallets: &mut [Wallet<WalletStore<'_>>],
+    destinations: &[Destination],
+    backend: &Backend,
+    fee: FeeStrategy,
+    max_fee_rate: f64,
+    include_unconfirmed: bool,
+    include_dust: bool,
+) -> Result<Sweep, Error> {
+    let (wallet, other_wallets) = match wallets.split_first_mut() {
+        Some(split) => split,
+        None => return Err(Error::Transaction("no wallets to consolidate".to_string())),
+    };
+    let fee = fee.resolve(backend).await?;
+    let dust_fee_rate = if include_dust { None } else { fee.fee_rate() };
+
+    // collect the coins of the other wallets with the information needed to sign them later
+    let mut total: u64 = sweepable_coins(wallet, include_unconfirmed, dust_fee_rate)
+        .iter()
+        .map(|utxo| utxo.txout.value)
+        .sum();
+    let mut foreign_utxos = Vec::new();
+    for other_wallet in other_wallets.iter() {
+        for utxo in sweepable_coins(other_wallet, include_unconfirmed, dust_fee_rate) {
+            total += utxo.txout.value;
+            let satisfaction_weight = match other_wallet
+                .get_descriptor_for_keychain(utxo.keychain)
+                .max_satisfaction_weight()
+            {
+                Ok(weight) => weight,
+                Err(e) => return Err(Error::Transaction(e.to_string())),
+            };
+            let outpoint = utxo.outpoint;
+            let psbt_input = match other_wallet.get_psbt_input(utxo, None, false) {
+                Ok(psbt_input) => psbt_input,
+                Err(e) => return Err(Error::Transaction(e.to_string())),
+            };
+            foreign_utxos.push((outpoint, psbt_input, satisfaction_weight));
+        }
+    }
+
+    let (recipients, address) = split_destinations(destinations, total)?;
+    let (mut psbt, details) = build_sweep(
+        wallet,
+        &recipients,
+        &address,
+        fee,
+        include_unconfirmed,
+        dust_fee_rate,
+        foreign_utxos,
+    )?;
+    for wallet in wallets.iter() {
+        if let Err(e) = wallet.sign(&mut psbt, SignOptions::default()) {
+            return Err(Error::Signing(e.to_string()));
+        }
+    }
+    // every input must have been finalized by the wallet that owns it
+    check_finalized(&psbt)?;
+    let sweep = Sweep {
+        psbt,
+        details,
+        recipients,
+        address,
+    };
+    check_max_fee_rate(&sweep, max_fee_rate)?;
+    Ok(sweep)
+}
+
+/// Build the transaction of a sweep spending all the coins of a wallet that can and should be swept,
+/// along with the coins of other wallets, to the shares of the recipients and the remainder to the address.
+/// The fee is set from the fee strategy, and the transaction is marked as RBF.
+fn build_sweep(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    recipients: &[(Address, u64)],
+    address: &Address,
+    fee: FeeStrategy,
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+    foreign_utxos: Vec<(OutPoint, psbt::Input, usize)>,
+) -> Result<(PartiallySignedTransaction, TransactionDetails), Error> {
+    let unspendable = unspendable_outpoints(wallet, include_unconfirmed, dust_fee_rate);
     let mut tx_builder = wallet.build_tx();
     tx_builder
         // Spend all outputs in this wallet.
         .drain_wallet()
-        // Send the excess (which is all the coins minus the fee) to this address.
+        // Except the ones that cannot or should not be swept.
+        .unspendable(unspendable)
+        // Send the shares of the destinations.
+        .set_recipients(
+            recipients
+                .iter()
+                .map(|(address, amount)| (address.script_pubkey(), *amount))
+                .collect(),
+        )
+        // Send the excess (which is all the coins minus the shares and the fee) to this address.
         .drain_to(address.script_pubkey())
-        .fee_rate(FeeRate::from_sat_per_vb(fee_rate))
+        // Keep the remainder as the last output, where a fee bump takes the fee increase from.
+        .ordering(TxOrdering::Untouched)
         .enable_rbf();
+    set_fee(&mut tx_builder, fee)?;
+    // Spend all outputs in the other wallets.
+    for (outpoint, psbt_input, satisfaction_weight) in foreign_utxos {
+        if let Err(e) = tx_builder.add_foreign_utxo(outpoint, psbt_input, satisfaction_weight) {
+            return Err(Error::Transaction(e.to_string()));
+        }
+    }
 
-    let (mut psbt, _) = match tx_builder.finish() {
+    match tx_builder.finish() {
+        Ok(psbt) => Ok(psbt),
+        Err(e) => Err(Error::Transaction(e.to_string())),
+    }
+}
+
+/// Create a Signed Transaction replacing an unconfirmed sweep with a higher fee (RBF).
+/// The wallet that knows the sweep rebuilds it spending the same coins,
+/// and the fee increase is taken from its last output, which receives the remainder of the sweep,
+/// while the other destinations keep their shares.
+/// The coins of the other wallets spent by a consolidated sweep are signed by their own wallet.
+/// The fee is chosen with the fee strategy, estimating the fee rate with the backend if needed,
+/// and the transaction is refused if its fee rate is above the maximum fee rate in sat/vB.
+pub async fn create_fee_bump_transaction(
+    wallets: &mut [Wallet<WalletStore<'_>>],
+    txid: Txid,
+    backend: &Backend,
+    fee: FeeStrategy,
+    max_fee_rate: f64,
+) -> Result<Sweep, Error> {
+    let fee = fee.resolve(backend).await?;
+    let (index, tx) = match wallets.iter().enumerate().find_map(|(index, wallet)| {
+        let tx = wallet.get_tx(txid, true)?.transaction?;
+        Some((index, tx))
+    }) {
+        Some(found) => found,
+        None => {
+            return Err(Error::Transaction(format!(
+                "transaction {} not found in the wallets",
+                txid
+            )))
+        }
+    };
+
+    // the wallet rebuilding the sweep needs the transactions of the coins of the other wallets
+    for txin in &tx.input {
+        let prev_txid = txin.previous_output.txid;
+        if wallets[index].get_tx(prev_txid, false).is_some() {
+            continue;
+        }
+        let prev_tx = wallets.iter().find_map(|wallet| {
+            let details = wallet.get_tx(prev_txid, true)?;
+            Some((details.transaction?, details.confirmation_time))
+        });
+        let (prev_tx, position) = match prev_tx {
+            Some(prev_tx) => prev_tx,
+            None => {
+                return Err(Error::Transaction(format!(
+                    "coin {} not found in the wallets",
+                    txin.previous_output
+                )))
+            }
+        };
+        if let Err(e) = wallets[index].insert_tx(prev_tx, position) {
+            return Err(Error::Transaction(e.to_string()));
+        }
+    }
+
+    let (script_pubkey, other_outputs) = match tx.output.split_last() {
+        Some((txout, other_outputs)) => (txout.script_pubkey.clone(), other_outputs),
+        None => return Err(Error::Transaction("transaction has no outputs".to_string())),
+    };
+    let network = wallets[index].network();
+    let address = match Address::from_script(&script_pubkey, network) {
+        Ok(address) => address,
+        Err(e) => return Err(Error::Address(e.to_string())),
+    };
+    // the shares of the other destinations are kept as they are
+    let recipients = other_outputs
+        .iter()
+        .map(
+            |txout| match Address::from_script(&txout.script_pubkey, network) {
+                Ok(address) => Ok((address, txout.value)),
+                Err(e) => Err(Error::Address(e.to_string())),
+            },
+        )
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut tx_builder = match wallets[index].build_fee_bump(txid) {
+        Ok(tx_builder) => tx_builder,
+        Err(e) => return Err(Error::Transaction(e.to_string())),
+    };
+    // Take the fee increase from the output receiving the remainder of the sweep.
+    if let Err(e) = tx_builder.allow_shrinking(script_pubkey) {
+        return Err(Error::Transaction(e.to_string()));
+    }
+    // Keep the remainder as the last output, for the next fee bump.
+    tx_builder.ordering(TxOrdering::Untouched).enable_rbf();
+    set_fee(&mut tx_builder, fee)?;
+
+    let (mut psbt, details) = match tx_builder.finish() {
         Ok(psbt) => psbt,
-        Err(e) => panic!("Error creating transaction: {}", e),
+        Err(e) => return Err(Error::Transaction(e.to_string())),
+    };
+    for wallet in wallets.iter() {
+        if let Err(e) = wallet.sign(&mut psbt, SignOptions::default()) {
+            return Err(Error::Signing(e.to_string()));
+        }
+    }
+    // every input must have been finalized by the wallet that owns it
+    check_finalized(&psbt)?;
+    let sweep = Sweep {
+        psbt,
+        details,
+        recipients,
+        address,
+    };
+    check_max_fee_rate(&sweep, max_fee_rate)?;
+    Ok(sweep)
+}
+
+/// Create a Signed Transaction accelerating an unconfirmed sweep to a wallet of ours (CPFP),
+/// spending the coins the sweep pays to the wallet back to one of its change addresses.
+/// With a fee rate or a confirmation target the child pays enough fee for the package
+/// of the sweep and the child to reach that fee rate, while an absolute fee is the fee of the child.
+/// The fee of the sweep is fetched from the backend, see [`create_cpfp_transaction_with_parent_fee`].
+/// The transaction is refused if the fee rate of the package is above the maximum fee rate in sat/vB.
+/// By default, the transaction is marked as RBF.
+pub async fn create_cpfp_transaction(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    txid: Txid,
+    backend: &Backend,
+    fee: FeeStrategy,
+    max_fee_rate: f64,
+) -> Result<Sweep, Error> {
+    let fee = fee.resolve(backend).await?;
+    let parent = unconfirmed_parent(wallet, txid)?;
+    let parent_fee = get_transaction_fee(backend, &parent).await?;
+    create_cpfp_transaction_with_parent_fee(wallet, txid, parent_fee, fee, max_fee_rate)
+}
+
+/// Create a Signed Transaction accelerating an unconfirmed sweep to a wallet of ours (CPFP)
+/// from the known fee of the sweep in sats, see [`create_cpfp_transaction`].
+/// The confirmation target of the fee strategy must have been resolved into a fee rate.
+pub fn create_cpfp_transaction_with_parent_fee(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    txid: Txid,
+    parent_fee: u64,
+    fee: FeeStrategy,
+    max_fee_rate: f64,
+) -> Result<Sweep, Error> {
+    let parent = unconfirmed_parent(wallet, txid)?;
+    let outpoints: Vec<OutPoint> = wallet
+        .list_unspent()
+        .filter(|utxo| utxo.outpoint.txid == txid)
+        .map(|utxo| utxo.outpoint)
+        .collect();
+    if outpoints.is_empty() {
+        return Err(Error::Transaction(format!(
+            "transaction {} pays no coins to the destination wallet",
+            txid
+        )));
+    }
+    let address = wallet.get_internal_address(AddressIndex::New).address;
+
+    // the size of the child does not depend on its fee
+    let (psbt, _) = create_child_transaction(wallet, &outpoints, address.script_pubkey(), 0)?;
+    let child_vsize = psbt.extract_tx().vsize() as u64;
+    let parent_vsize = parent.vsize() as u64;
+    let child_fee = match fee {
+        FeeStrategy::Rate(fee_rate) => cpfp_fee(fee_rate, parent_fee, parent_vsize, child_vsize),
+        FeeStrategy::Absolute(fee) => fee,
+        FeeStrategy::Target(blocks) => {
+            return Err(Error::Transaction(format!(
+                "no fee rate estimated for the target of {} blocks",
+                blocks
+            )))
+        }
     };
-    match wallet.sign(&mut psbt, SignOptions::default()) {
-        Ok(finalized) => finalized,
-        Err(e) => panic!("Error signing transaction: {}", e),
+
+    let (psbt, details) =
+        create_child_transaction(wallet, &outpoints, address.script_pubkey(), child_fee)?;
+    let sweep = Sweep {
+        psbt,
+        details,
+        recipients: Vec::new(),
+        address,
     };
-    psbt
+    let package_fee_rate =
+        (parent_fee + sweep.fee()) as f64 / (parent_vsize + sweep.vsize() as u64) as f64;
+    if package_fee_rate > max_fee_rate {
+        return Err(Error::Transaction(format!(
+            "package fee rate of {:.2} sat/vB is above the maximum of {:.2} sat/vB",
+            package_fee_rate, max_fee_rate
+        )));
+    }
+    Ok(sweep)
+}
+
+/// The unconfirmed sweep to accelerate, as known by the destination wallet.
+fn unconfirmed_parent(wallet: &Wallet<WalletStore<'_>>, txid: Txid) -> Result<Transaction, Error> {
+    match wallet.get_tx(txid, true) {
+        Some(TransactionDetails {
+            transaction: Some(tx),
+            confirmation_time: ConfirmationTime::Unconfirmed { .. },
+            ..
+        }) => Ok(tx),
+        Some(_) => Err(Error::Transaction(format!(
+            "transaction {} is already confirmed",
+            txid
+        ))),
+        None => Err(Error::Transaction(format!(
+            "transaction {} not found in the destination wallet",
+            txid
+        ))),
+    }
 }
 
-/// Broadcast a signed transaction to the network using the given Esplora client.
-pub async fn broadcast_signed_transaction(psbt: PartiallySignedTransaction, client: &AsyncClient) {
+/// The fee in sats of a child for the package with its parent to reach a fee rate in sat/vB,
+/// never below the minimum relay fee rate of the child alone.
+pub fn cpfp_fee(fee_rate: f64, parent_fee: u64, parent_vsize: u64, child_vsize: u64) -> u64 {
+    let package_fee = (fee_rate * (parent_vsize + child_vsize) as f64).ceil() as u64;
+    let min_fee = (MIN_RELAY_FEE_RATE * child_vsize as f64).ceil() as u64;
+    package_fee.saturating_sub(parent_fee).max(min_fee)
+}
+
+/// Create a Signed Transaction spending only the given coins of a wallet to a script
+/// with an absolute fee.
+fn create_child_transaction(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    outpoints: &[OutPoint],
+    script_pubkey: Script,
+    fee: u64,
+) -> Result<(PartiallySignedTransaction, TransactionDetails), Error> {
+    let mut tx_builder = wallet.build_tx();
+    if let Err(e) = tx_builder.add_utxos(outpoints) {
+        return Err(Error::Transaction(e.to_string()));
+    }
+    tx_builder
+        // Spend only the given coins.
+        .manually_selected_only()
+        // Send the excess (which is all the coins minus the fee) to this script.
+        .drain_to(script_pubkey)
+        .fee_absolute(fee)
+        .enable_rbf();
+
+    let (mut psbt, details) = match tx_builder.finish() {
+        Ok(psbt) => psbt,
+        Err(e) => return Err(Error::Transaction(e.to_string())),
+    };
+    if let Err(e) = wallet.sign(&mut psbt, SignOptions::default()) {
+        return Err(Error::Signing(e.to_string()));
+    }
+    // a watch-only wallet cannot sign, and the size of an unsigned child would be too small
+    check_finalized(&psbt)?;
+    Ok((psbt, details))
+}
+
+/// Check that every input of a transaction was signed and finalized.
+fn check_finalized(psbt: &PartiallySignedTransaction) -> Result<(), Error> {
+    let finalized = psbt
+        .inputs
+        .iter()
+        .all(|input| input.final_script_sig.is_some() || input.final_script_witness.is_some());
+    if !finalized {
+        return Err(Error::Signing("not all inputs could be signed".to_string()));
+    }
+    Ok(())
+}
+
+/// Check that the fee rate of a sweep is not above the maximum fee rate in sat/vB,
+/// to prevent overpaying by mistake.
+fn check_max_fee_rate(sweep: &Sweep, max_fee_rate: f64) -> Result<(), Error> {
+    if sweep.fee_rate() > max_fee_rate {
+        return Err(Error::Transaction(format!(
+            "fee rate of {:.2} sat/vB is above the maximum of {:.2} sat/vB",
+            sweep.fee_rate(),
+            max_fee_rate
+        )));
+    }
+    Ok(())
+}
+
+/// Broadcast a signed transaction to the network using the given backend.
+/// Returns the txid of the broadcasted transaction.
+pub async fn broadcast_signed_transaction(
+    psbt: PartiallySignedTransaction,
+    backend: &Backend,
+) -> Result<Txid, Error> {
     let tx = psbt.extract_tx();
-    match client.broadcast(&tx).await {
-        Ok(_) => println!("Transaction sent!"),
-        Err(e) => panic!("Error broadcasting transaction: {}", e),
+    let broadcast = match backend {
+        Backend::Esplora(client) => client.broadcast(&tx).await.map_err(|e| e.to_string()),
+        Backend::Electrum(client) => client
+            .transaction_broadcast(&tx)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Backend::Rpc(client) => client
+            .send_raw_transaction(&tx)
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        Backend::Cbf(handle) => handle
+            .submit_transaction(tx.clone())
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
     };
-    println!("Tx broadcasted! Txid: {}", tx.txid());
+    match broadcast {
+        Ok(()) => Ok(tx.txid()),
+        Err(e) => Err(Error::Broadcast(e)),
+    }
+}
+
+/// Get the fee of a transaction in sats, fetching the coins it spends from the backend,
+/// or from its mempool entry with the rpc backend, which needs no index for unconfirmed transactions.
+pub async fn get_transaction_fee(backend: &Backend, tx: &Transaction) -> Result<u64, Error> {
+    let mut input_value = 0;
+    for txin in &tx.input {
+        let prevout = txin.previous_output;
+        let prev_tx = match backend {
+            Backend::Esplora(client) => match client.get_tx(&prevout.txid).await {
+                Ok(prev_tx) => prev_tx,
+                Err(e) => return Err(Error::Sync(e.to_string())),
+            },
+            Backend::Electrum(client) => match client.transaction_get(&prevout.txid) {
+                Ok(prev_tx) => Some(prev_tx),
+                Err(e) => return Err(Error::Sync(e.to_string())),
+            },
+            Backend::Rpc(client) => {
+                return match client.get_mempool_entry(&tx.txid()) {
+                    Ok(entry) => Ok(entry.fees.base.to_sat()),
+                    Err(e) => Err(Error::Sync(e.to_string())),
+                }
+            }
+            Backend::Cbf(_) => {
+                return Err(Error::Sync(
+                    "the cbf backend does not provide the fee of transactions".to_string(),
+                ))
+            }
+        };
+        match prev_tx.and_then(|prev_tx| prev_tx.output.get(prevout.vout as usize).cloned()) {
+            Some(txout) => input_value += txout.value,
+            None => return Err(Error::Sync(format!("coin {} not found", prevout))),
+        }
+    }
+    let output_value: u64 = tx.output.iter().map(|txout| txout.value).sum();
+    match input_value.checked_sub(output_value) {
+        Some(fee) => Ok(fee),
+        None => Err(Error::Sync(format!(
+            "transaction {} spends more than its coins",
+            tx.txid()
+        ))),
+    }
 }
 
-/// Sync a wallet with the Esplora client.
-pub async fn sync_wallet(wallet: &mut Wallet<Store<'_, ChangeSet>>, client: &AsyncClient) {
+/// Sync a wallet with the given backend.
+pub async fn sync_wallet(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    backend: &Backend,
+) -> Result<(), Error> {
     let local_chain = wallet.checkpoints();
 
     let keychain_spks = wallet.spks_of_all_keychains().into_iter().collect();
-    let update = client
-        .scan(
-            local_chain,
-            keychain_spks,
-            [],
-            [],
-            STOP_GAP,
-            PARALLEL_REQUESTS,
-        )
-        .await;
-    match update {
-        Ok(update) => {
-            wallet.apply_update(update).unwrap();
-            wallet.commit().unwrap();
+    let update = match backend {
+        Backend::Esplora(client) => match client
+            .scan(
+                local_chain,
+                keychain_spks,
+                [],
+                [],
+                STOP_GAP,
+                PARALLEL_REQUESTS,
+            )
+            .await
+        {
+            Ok(update) => update,
+            Err(e) => return Err(Error::Sync(e.to_string())),
+        },
+        Backend::Electrum(client) => {
+            let update = match client.scan(local_chain, keychain_spks, [], [], STOP_GAP, BATCH_SIZE)
+            {
+                Ok(update) => update,
+                Err(e) => return Err(Error::Sync(e.to_string())),
+            };
+            // the electrum update only has the txids, so fetch the transactions the wallet is missing
+            let missing = update.missing_full_txs(wallet.as_ref());
+            match update.finalize_as_confirmation_time(client, None, missing) {
+                Ok(update) => update,
+                Err(e) => return Err(Error::Sync(e.to_string())),
+            }
+        }
+        Backend::Rpc(client) => match scan_utxos(&[&*wallet], client)?.pop() {
+            Some(update) => update,
+            None => return Err(Error::Sync("no update for the wallet".to_string())),
+        },
+        Backend::Cbf(handle) => match scan_filters(&[&*wallet], handle).await?.pop() {
+            Some(update) => update,
+            None => return Err(Error::Sync("no update for the wallet".to_string())),
+        },
+    };
+    apply_sync_update(wallet, update)
+}
+
+/// Sync several wallets with the given backend.
+/// The wallets are synced with parallel async requests,
+/// except with the rpc backend, where a single scan of the UTXO set finds the coins of every wallet,
+/// and the cbf backend, where a single rescan matches the filters against the scripts of every wallet.
+pub async fn sync_wallets(
+    wallets: Vec<&mut Wallet<WalletStore<'_>>>,
+    backend: &Backend,
+) -> Result<(), Error> {
+    let updates = match backend {
+        Backend::Rpc(client) => scan_utxos(
+            &wallets.iter().map(|wallet| &**wallet).collect::<Vec<_>>(),
+            client,
+        )?,
+        Backend::Cbf(handle) => {
+            scan_filters(
+                &wallets.iter().map(|wallet| &**wallet).collect::<Vec<_>>(),
+                handle,
+            )
+            .await?
+        }
+        Backend::Esplora(_) | Backend::Electrum(_) => {
+            let tasks = wallets
+                .into_iter()
+                .map(|wallet| sync_wallet(wallet, backend))
+                .collect::<Vec<_>>();
+            return join_all(tasks).await.into_iter().collect();
         }
-        Err(e) => panic!("Error syncing wallet: {}", e),
+    };
+    for (wallet, update) in wallets.into_iter().zip(updates) {
+        apply_sync_update(wallet, update)?;
+    }
+    Ok(())
+}
+
+/// Apply the update of a sync to a wallet and persist it.
+fn apply_sync_update(
+    wallet: &mut Wallet<WalletStore<'_>>,
+    update: LocalUpdate<KeychainKind, ConfirmationTimeAnchor>,
+) -> Result<(), Error> {
+    if let Err(e) = wallet.apply_update(update) {
+        return Err(Error::Sync(e.to_string()));
+    }
+    match wallet.commit() {
+        Ok(_) => Ok(()),
+        Err(e) => Err(Error::Wallet(e.to_string())),
+    }
+}
+
+/// Scan the coins of several wallets in the UTXO set of a bitcoin core node
+/// with a single `scantxoutset` request for the descriptors of every wallet,
+/// since each request goes through the whole UTXO set.
+/// The node keeps no history of the scripts, so only confirmed unspent coins are found,
+/// and the wallets must not be persisted or the coins spent since the last run would still look unspent.
+fn scan_utxos(
+    wallets: &[&Wallet<WalletStore<'_>>],
+    client: &RpcClient,
+) -> Result<Vec<LocalUpdate<KeychainKind, ConfirmationTimeAnchor>>, Error> {
+    let rpc_error = |e: bitcoincore_rpc::Error| Error::Sync(e.to_string());
+
+    let scripts: Vec<_> = wallets
+        .iter()
+        .map(|wallet| scanned_scripts(wallet))
+        .collect();
+    let requests: Vec<_> = wallets
+        .iter()
+        .flat_map(|wallet| {
+            wallet
+                .spks_of_all_keychains()
+                .into_keys()
+                .map(|keychain| ScanTxOutRequest::Extended {
+                    desc: wallet.get_descriptor_for_keychain(keychain).to_string(),
+                    range: (0, SCAN_RANGE as u64 - 1),
+                })
+        })
+        .collect();
+    let result = client
+        .scan_tx_out_set_blocking(&requests)
+        .map_err(rpc_error)?;
+    let tip = match (result.height, result.best_block_hash) {
+        (Some(height), Some(hash)) => BlockId {
+            height: height as u32,
+            hash,
+        },
+        _ => return Err(Error::Sync("the node did not return its tip".to_string())),
+    };
+
+    let mut block_hashes = HashMap::new();
+    let mut block_hash = |height: u64| match block_hashes.get(&height) {
+        Some(hash) => Ok(*hash),
+        None => {
+            let hash = client.get_block_hash(height).map_err(rpc_error)?;
+            block_hashes.insert(height, hash);
+            Ok::<_, Error>(hash)
+        }
+    };
+
+    let mut updates = Vec::with_capacity(wallets.len());
+    for wallet in wallets {
+        let mut update = LocalUpdate::default();
+        // the blocks of the local chain up to the point of agreement, so that the update connects to it
+        for (&height, &hash) in wallet.checkpoints().iter().rev() {
+            let hash_at_height = block_hash(height as u64)?;
+            let _ = update.chain.insert_block(BlockId {
+                height,
+                hash: hash_at_height,
+            });
+            if hash_at_height == hash {
+                break;
+            }
+        }
+        let _ = update.chain.insert_block(tip);
+        updates.push(update);
+    }
+
+    for utxo in result.unspents {
+        let owners: Vec<_> = scripts
+            .iter()
+            .enumerate()
+            .filter_map(|(position, scripts)| {
+                let &(keychain, index) = scripts.get(&utxo.script_pub_key)?;
+                Some((position, keychain, index))
+            })
+            .collect();
+        if owners.is_empty() {
+            continue;
+        }
+        let hash = block_hash(utxo.height)?;
+        let block_header = client.get_block_header(&hash).map_err(rpc_error)?;
+        // the block hash allows getting the transaction without a transaction index
+        let tx = client
+            .get_raw_transaction(&utxo.txid, Some(&hash))
+            .map_err(rpc_error)?;
+        for (position, keychain, index) in owners {
+            let update = &mut updates[position];
+            let _ = update.graph.insert_tx(tx.clone());
+            let _ = update.graph.insert_anchor(
+                utxo.txid,
+                ConfirmationTimeAnchor {
+                    anchor_block: tip,
+                    confirmation_height: utxo.height as u32,
+                    confirmation_time: block_header.time as u64,
+                },
+            );
+            let last_index = update.keychain.entry(keychain).or_insert(index);
+            *last_index = index.max(*last_index);
+        }
+    }
+    Ok(updates)
+}
+
+/// What the compact block filters scan needs from a wallet,
+/// owned so that the scan can run on a blocking thread.
+struct FilterScan {
+    /// The scripts of the wallet with their keychain and index.
+    scripts: HashMap<Script, (KeychainKind, u32)>,
+    /// The blocks of the local chain of the wallet.
+    checkpoints: BTreeMap<u32, BlockHash>,
+    /// The coins of the wallet, to find the transactions that spend them.
+    outpoints: HashSet<OutPoint>,
+}
+
+/// Scan the coins of several wallets matching the compact block filters locally
+/// with a single rescan for the scripts of every wallet,
+/// fetching only the blocks that match the scripts of the wallets.
+/// The scan blocks on the light client, so it runs on a blocking thread.
+async fn scan_filters(
+    wallets: &[&Wallet<WalletStore<'_>>],
+    handle: &CbfHandle,
+) -> Result<Vec<LocalUpdate<KeychainKind, ConfirmationTimeAnchor>>, Error> {
+    let scans = wallets
+        .iter()
+        .map(|wallet| FilterScan {
+            scripts: scanned_scripts(wallet),
+            checkpoints: wallet.checkpoints().clone(),
+            outpoints: wallet.list_unspent().map(|utxo| utxo.outpoint).collect(),
+        })
+        .collect();
+    let handle = handle.clone();
+    match task::spawn_blocking(move || match_filters(&handle, scans)).await {
+        Ok(updates) => updates,
+        Err(e) => Err(Error::Sync(e.to_string())),
+    }
+}
+
+/// Match the compact block filters against the scripts of every scan, from the lowest last checkpoint.
+/// Every wait for the peer times out after [`CBF_TIMEOUT`].
+fn match_filters(
+    handle: &CbfHandle,
+    scans: Vec<FilterScan>,
+) -> Result<Vec<LocalUpdate<KeychainKind, ConfirmationTimeAnchor>>, Error> {
+    let cbf_error = |e: nakamoto::client::handle::Error| Error::Sync(e.to_string());
+
+    // wait for a peer serving compact block filters and for the block headers up to its tip,
+    // both timing out with the timeout of the handle
+    let peers = handle
+        .wait_for_peers(1, ServiceFlags::COMPACT_FILTERS)
+        .map_err(cbf_error)?;
+    let peer_height = peers
+        .iter()
+        .map(|(_, height, _)| *height)
+        .max()
+        .unwrap_or(0);
+    handle.wait_for_height(peer_height).map_err(cbf_error)?;
+
+    // the blocks before the last checkpoint of every wallet were already scanned in a previous sync
+    let events = handle.events();
+    let start = scans
+        .iter()
+        .map(|scan| {
+            scan.checkpoints
+                .keys()
+                .next_back()
+                .map_or(0, |height| *height as u64)
+        })
+        .min()
+        .unwrap_or(0);
+    let scripts: HashSet<Script> = scans
+        .iter()
+        .flat_map(|scan| scan.scripts.keys().cloned())
+        .collect();
+    handle
+        .rescan(start.., scripts.into_iter())
+        .map_err(cbf_error)?;
+
+    // the filters are processed before the matching blocks are fetched,
+    // so wait for both the filters to be synced and every matching block to arrive
+    let mut pending = HashSet::new();
+    let mut blocks = BTreeMap::new();
+    let mut synced = false;
+    while !synced || !pending.is_empty() {
+        match events.recv_timeout(CBF_TIMEOUT) {
+            Ok(Event::FilterProcessed {
+                height,
+                matched: true,
+                ..
+            }) if !blocks.contains_key(&height) => {
+                pending.insert(height);
+            }
+            Ok(Event::BlockMatched {
+                height,
+                header,
+                transactions,
+                ..
+            }) => {
+                pending.remove(&height);
+                blocks.insert(height, (header, transactions));
+            }
+            Ok(Event::Synced { height, tip }) => synced = height >= peer_height && height == tip,
+            Ok(_) => {}
+            Err(_) => {
+                return Err(Error::Sync(format!(
+                    "no answer from the peer in {} seconds",
+                    CBF_TIMEOUT.as_secs()
+                )))
+            }
+        }
+    }
+    let (tip_height, tip_header) = handle.get_tip().map_err(cbf_error)?;
+    let tip = BlockId {
+        height: tip_height as u32,
+        hash: tip_header.block_hash(),
+    };
+
+    let mut updates = Vec::with_capacity(scans.len());
+    for mut scan in scans {
+        let mut update = LocalUpdate::default();
+        // the blocks of the local chain up to the point of agreement, so that the update connects to it
+        for (&height, &hash) in scan.checkpoints.iter().rev() {
+            if let Some(header) = handle
+                .get_block_by_height(height as u64)
+                .map_err(cbf_error)?
+            {
+                let _ = update.chain.insert_block(BlockId {
+                    height,
+                    hash: header.block_hash(),
+                });
+                if header.block_hash() == hash {
+                    break;
+                }
+            }
+        }
+        let _ = update.chain.insert_block(tip);
+
+        for (height, (header, transactions)) in &blocks {
+            for tx in transactions {
+                let txid = tx.txid();
+                let spends = tx
+                    .input
+                    .iter()
+                    .any(|input| scan.outpoints.contains(&input.previous_output));
+                let mut receives = false;
+                for (vout, output) in tx.output.iter().enumerate() {
+                    if let Some(&(keychain, index)) = scan.scripts.get(&output.script_pubkey) {
+                        receives = true;
+                        scan.outpoints.insert(OutPoint::new(txid, vout as u32));
+                        let last_index = update.keychain.entry(keychain).or_insert(index);
+                        *last_index = index.max(*last_index);
+                    }
+                }
+                // blocks match the filters of many other scripts, keep only the wallet's transactions
+                if !spends && !receives {
+                    continue;
+                }
+                let _ = update.graph.insert_tx(tx.clone());
+                let _ = update.graph.insert_anchor(
+                    txid,
+                    ConfirmationTimeAnchor {
+                        anchor_block: tip,
+                        confirmation_height: *height as u32,
+                        confirmation_time: header.time as u64,
+                    },
+                );
+            }
+        }
+        updates.push(update);
     }
+    Ok(updates)
 }
 
-/// Check if a wallet has any coins to spend.
-pub fn check_balance(wallet: &Wallet<Store<ChangeSet>>) -> bool {
+/// The keychain and index of the scripts scanned by the backends that cannot stop at a gap,
+/// to reveal the ones that have coins.
+fn scanned_scripts(wallet: &Wallet<WalletStore<'_>>) -> HashMap<Script, (KeychainKind, u32)> {
+    wallet
+        .spks_of_all_keychains()
+        .into_iter()
+        .flat_map(|(keychain, spks)| {
+            spks.take(SCAN_RANGE as usize)
+                .map(move |(index, script)| (script, (keychain, index)))
+        })
+        .collect()
+}
+
+/// Check if a wallet has any coins to spend,
+/// counting the unconfirmed ones only if they are included in the sweep.
+/// Immature coinbase outputs cannot be spent and never count.
+pub fn check_balance(wallet: &Wallet<WalletStore>, include_unconfirmed: bool) -> bool {
     // no need to check for lower than 0 since it is unsigned
     let balance = wallet.get_balance();
-    !matches!(balance.confirmed, 0)
+    let pending = balance.trusted_pending + balance.untrusted_pending;
+    !matches!(balance.confirmed, 0) || (include_unconfirmed && !matches!(pending, 0))
 }
 
-/// Get the fee estimates from the Esplora server.
-/// The default block is 1, which is the next block.
-pub async fn get_fee_estimates(client: &AsyncClient, block: Option<u64>) -> f32 {
-    let fee_estimates: HashMap<String, f64> = match client.get_fee_estimates().await {
-        Ok(future) => future,
-        Err(e) => panic!("Error getting fee estimates: {}", e),
+/// The coins of a wallet left behind by a sweep.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LeftBehind {
+    /// Coinbase outputs in sats, which cannot be spent until they are 100 blocks deep.
+    pub immature: u64,
+    /// Unconfirmed coins in sats, either our own change or received from others.
+    pub unconfirmed: u64,
+    /// Coins that cost more to spend than they are worth, with their value in sats.
+    pub dust: Vec<(OutPoint, u64)>,
+}
+
+/// The coins a sweep of a wallet leaves behind,
+/// including the unconfirmed ones unless they are included in the sweep,
+/// and the uneconomical ones at the dust fee rate in sat/vB, if any.
+pub fn left_behind(
+    wallet: &Wallet<WalletStore>,
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+) -> LeftBehind {
+    let balance = wallet.get_balance();
+    let dust = match dust_fee_rate {
+        Some(fee_rate) => sweepable_coins(wallet, include_unconfirmed, None)
+            .into_iter()
+            .filter(|utxo| is_uneconomical(wallet, utxo, fee_rate))
+            .map(|utxo| (utxo.outpoint, utxo.txout.value))
+            .collect(),
+        None => Vec::new(),
+    };
+    LeftBehind {
+        immature: balance.immature,
+        unconfirmed: if include_unconfirmed {
+            0
+        } else {
+            balance.trusted_pending + balance.untrusted_pending
+        },
+        dust,
+    }
+}
+
+/// The coins of a wallet a sweep spends: the confirmed ones, and the unconfirmed ones if included,
+/// but neither immature coinbase outputs nor the uneconomical coins at the dust fee rate in sat/vB, if any.
+pub fn sweepable_coins(
+    wallet: &Wallet<WalletStore>,
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+) -> Vec<LocalUtxo> {
+    wallet
+        .list_unspent()
+        .filter(|utxo| {
+            include_unconfirmed
+                || matches!(utxo.confirmation_time, ConfirmationTime::Confirmed { .. })
+        })
+        .filter(|utxo| !is_immature(wallet, utxo))
+        .filter(|utxo| match dust_fee_rate {
+            Some(fee_rate) => !is_uneconomical(wallet, utxo, fee_rate),
+            None => true,
+        })
+        .collect()
+}
+
+/// Check if the coins a sweep of the wallets spends cover the shares of the destinations,
+/// the fee and a remainder above the dust limit, with the fee estimated for the largest common scripts.
+/// If they do not, gets their value and the estimated fee in sats, since the sweep would fail.
+pub fn below_fee(
+    wallets: &[&Wallet<WalletStore>],
+    destinations: &[Destination],
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+    fee: FeeStrategy,
+) -> Option<(u64, u64)> {
+    let coins: Vec<(&Wallet<WalletStore>, LocalUtxo)> = wallets
+        .iter()
+        .flat_map(|wallet| {
+            sweepable_coins(wallet, include_unconfirmed, dust_fee_rate)
+                .into_iter()
+                .map(move |utxo| (*wallet, utxo))
+        })
+        .collect();
+    let value: u64 = coins.iter().map(|(_, utxo)| utxo.txout.value).sum();
+    let estimated_fee = match fee {
+        FeeStrategy::Absolute(fee) => fee,
+        fee => {
+            let weight = TX_BASE_WEIGHT
+                + TXOUT_WEIGHT * destinations.len()
+                + coins
+                    .iter()
+                    .map(|(wallet, utxo)| {
+                        TXIN_BASE_WEIGHT
+                            + wallet
+                                .get_descriptor_for_keychain(utxo.keychain)
+                                .max_satisfaction_weight()
+                                .unwrap_or_default()
+                    })
+                    .sum::<usize>();
+            (weight as f64 / 4.0 * fee.fee_rate().unwrap_or(MIN_RELAY_FEE_RATE)).ceil() as u64
+        }
     };
-    let fee_estimate = match block {
-        Some(block) => fee_estimates.get(&block.to_string()).unwrap(),
-        None => fee_estimates.get("1").unwrap(),
+    let shares: u64 = destinations
+        .iter()
+        .map(|destination| match destination.share {
+            Some(Share::Amount(amount)) => amount,
+            Some(Share::Percent(percent)) => (value as f64 * percent / 100.0).floor() as u64,
+            None => 0,
+        })
+        .sum();
+    if value > shares + estimated_fee + DUST_LIMIT {
+        None
+    } else {
+        Some((value, estimated_fee))
+    }
+}
+
+/// Check if spending a coin of a wallet costs at least its value at a fee rate in sat/vB.
+pub fn is_uneconomical(wallet: &Wallet<WalletStore>, utxo: &LocalUtxo, fee_rate: f64) -> bool {
+    let satisfaction_weight = wallet
+        .get_descriptor_for_keychain(utxo.keychain)
+        .max_satisfaction_weight()
+        .unwrap_or_default();
+    let vsize = (TXIN_BASE_WEIGHT + satisfaction_weight) as f64 / 4.0;
+    (vsize * fee_rate).ceil() as u64 >= utxo.txout.value
+}
+
+/// Check if a coin of a wallet is a coinbase output that cannot be spent yet.
+fn is_immature(wallet: &Wallet<WalletStore>, utxo: &LocalUtxo) -> bool {
+    let is_coinbase = wallet
+        .get_tx(utxo.outpoint.txid, true)
+        .and_then(|details| details.transaction)
+        .is_some_and(|tx| tx.is_coin_base());
+    let tip = wallet.checkpoints().keys().next_back().copied();
+    match (utxo.confirmation_time, tip) {
+        (ConfirmationTime::Confirmed { height, .. }, Some(tip)) => {
+            // the same rule as the balance of the wallet, counting the block of the coin itself
+            is_coinbase && tip.saturating_sub(height) + 1 < COINBASE_MATURITY
+        }
+        _ => is_coinbase,
+    }
+}
+
+/// The coins of a wallet a sweep does not spend, see [`sweepable_coins`].
+fn unspendable_outpoints(
+    wallet: &Wallet<WalletStore>,
+    include_unconfirmed: bool,
+    dust_fee_rate: Option<f64>,
+) -> Vec<OutPoint> {
+    let sweepable: HashSet<OutPoint> = sweepable_coins(wallet, include_unconfirmed, dust_fee_rate)
+        .into_iter()
+        .map(|utxo| utxo.outpoint)
+        .collect();
+    wallet
+        .list_unspent()
+        .map(|utxo| utxo.outpoint)
+        .filter(|outpoint| !sweepable.contains(outpoint))
+        .collect()
+}
+
+/// Check if a wallet has any transaction history, even if all its coins were spent.
+pub fn has_history(wallet: &Wallet<WalletStore>) -> bool {
+    wallet.transactions().next().is_some()
+}
+
+/// Get the fee estimate in sat/vB from the backend.
+/// The default block is 1, which is the next block.
+/// Without an estimate for the block, Esplora and Electrum fall back to the nearest lower target,
+/// while Bitcoin Core does the fallback itself.
+/// The estimate is never below the minimum relay fee rate.
+pub async fn get_fee_estimates(backend: &Backend, block: Option<u64>) -> Result<f64, Error> {
+    let block = block.unwrap_or(1);
+    let fee_estimate = match backend {
+        Backend::Esplora(client) => {
+            let fee_estimates: HashMap<String, f64> = match client.get_fee_estimates().await {
+                Ok(future) => future,
+                Err(e) => return Err(Error::FeeEstimates(e.to_string())),
+            };
+            select_fee_estimate(&fee_estimates, block)?
+        }
+        Backend::Electrum(client) => {
+            // the targets Esplora servers estimate, up to the block, in a single batch
+            let targets: Vec<u64> = (1..=25)
+                .chain([144, 504, 1008])
+                .filter(|target| *target < block)
+                .chain([block])
+                .collect();
+            let fee_estimates =
+                match client.batch_estimate_fee(targets.iter().map(|target| *target as usize)) {
+                    Ok(fee_estimates) => fee_estimates,
+                    Err(e) => return Err(Error::FeeEstimates(e.to_string())),
+                };
+            select_fee_estimate(&electrum_fee_estimates(&targets, &fee_estimates), block)?
+        }
+        // bitcoin core estimates up to 1008 blocks, and larger targets would overflow the request
+        Backend::Rpc(client) => match client.estimate_smart_fee(block.min(1_008) as u16, None) {
+            // bitcoin core estimates in BTC/kvB
+            Ok(EstimateSmartFeeResult {
+                fee_rate: Some(fee_rate),
+                ..
+            }) => fee_rate.to_sat() as f64 / 1_000.0,
+            Ok(_) => {
+                return Err(Error::FeeEstimates(format!(
+                    "no estimate for a {} block target",
+                    block
+                )))
+            }
+            Err(e) => return Err(Error::FeeEstimates(e.to_string())),
+        },
+        Backend::Cbf(_) => {
+            return Err(Error::FeeEstimates(
+                "the cbf backend does not provide fee estimates".to_string(),
+            ))
+        }
     };
-    *fee_estimate as f32
+    Ok(fee_estimate.max(MIN_RELAY_FEE_RATE))
+}
+
+/// Convert the fee estimates of an Electrum server, in BTC/kvB for each of the targets in blocks,
+/// into fee rates in sat/vB keyed by their target like the estimates of an Esplora server.
+/// The server returns a negative value for the targets it has no estimate for, which are omitted.
+pub fn electrum_fee_estimates(targets: &[u64], fee_estimates: &[f64]) -> HashMap<String, f64> {
+    targets
+        .iter()
+        .zip(fee_estimates)
+        .filter(|(_, fee_estimate)| **fee_estimate > 0.0)
+        .map(|(target, fee_estimate)| (target.to_string(), fee_estimate * 100_000.0))
+        .collect()
+}
+
+/// Select the fee rate in sat/vB to confirm within a number of blocks
+/// from the fee estimates of an Esplora or Electrum server, keyed by their target in blocks.
+/// Servers omit the targets they have no estimate for,
+/// in which case the estimate of the nearest lower target is used.
+/// The fee rate is never below the minimum relay fee rate.
+pub fn select_fee_estimate(fee_estimates: &HashMap<String, f64>, block: u64) -> Result<f64, Error> {
+    if fee_estimates.is_empty() {
+        return Err(Error::FeeEstimates(
+            "the server returned no estimates".to_string(),
+        ));
+    }
+    let fee_estimate = fee_estimates
+        .iter()
+        .filter_map(|(target, fee_rate)| match target.parse::<u64>() {
+            Ok(target) if target <= block && fee_rate.is_finite() => Some((target, *fee_rate)),
+            _ => None,
+        })
+        .max_by_key(|(target, _)| *target);
+    match fee_estimate {
+        Some((_, fee_rate)) => Ok(fee_rate.max(MIN_RELAY_FEE_RATE)),
+        None => Err(Error::FeeEstimates(format!(
+            "no estimate for a {} block target or lower",
+            block
+        ))),
+    }
 }
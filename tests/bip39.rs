@@ -1,6 +1,6 @@
-use bdk::keys::bip39::Mnemonic;
+use bdk::keys::bip39::{Language, Mnemonic};
 use std::any::TypeId;
-use sweepr::bip39::parse_mnemonic;
+use sweepr::bip39::{create_language, parse_mnemonic};
 
 fn is_mnemonic<T: ?Sized + 'static>(_s: &T) -> bool {
     TypeId::of::<Mnemonic>() == TypeId::of::<T>()
@@ -10,22 +10,64 @@ fn is_mnemonic<T: ?Sized + 'static>(_s: &T) -> bool {
 fn test_mnemonic() {
     let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
     let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
-    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12);
-    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24);
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, Some(Language::English)).unwrap();
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
     assert!(is_mnemonic(&parsed_mnemonic_12));
     assert!(is_mnemonic(&parsed_mnemonic_24));
 }
 
 #[test]
-#[should_panic]
 fn test_invalid_mnemonic_12() {
     let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
-    parse_mnemonic(mnemonic);
+    assert!(parse_mnemonic(mnemonic, None).is_err());
 }
 
 #[test]
-#[should_panic]
 fn test_invalid_mnemonic_24() {
     let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon";
-    parse_mnemonic(mnemonic);
+    assert!(parse_mnemonic(mnemonic, None).is_err());
+}
+
+#[test]
+fn test_mnemonic_languages() {
+    let spanish = "ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco ábaco abierto";
+    let japanese = "あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あいこくしん　あおぞら";
+    let chinese = "的 的 的 的 的 的 的 的 的 的 的 在";
+
+    let parsed_spanish = parse_mnemonic(spanish, None).unwrap();
+    let parsed_japanese = parse_mnemonic(japanese, None).unwrap();
+    let parsed_chinese = parse_mnemonic(chinese, None).unwrap();
+    assert_eq!(parsed_spanish.language(), Language::Spanish);
+    assert_eq!(parsed_japanese.language(), Language::Japanese);
+    assert!(matches!(
+        parsed_chinese.language(),
+        Language::SimplifiedChinese | Language::TraditionalChinese
+    ));
+
+    // all of them encode the same entropy
+    assert_eq!(parsed_spanish.to_entropy(), parsed_japanese.to_entropy());
+    assert_eq!(parsed_spanish.to_entropy(), parsed_chinese.to_entropy());
+
+    assert!(parse_mnemonic(spanish, Some(Language::Spanish)).is_ok());
+    assert!(parse_mnemonic(spanish, Some(Language::English)).is_err());
+}
+
+#[test]
+fn test_invalid_mnemonic_unknown_words() {
+    let mnemonic = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon sweepr hodlr";
+    let error = parse_mnemonic(mnemonic, None).unwrap_err().to_string();
+    assert!(error.contains("English"));
+    assert!(error.contains("sweepr, hodlr"));
+}
+
+#[test]
+fn test_create_language() {
+    assert_eq!(create_language("english").unwrap(), Language::English);
+    assert_eq!(create_language("Spanish").unwrap(), Language::Spanish);
+    assert_eq!(create_language("japanese").unwrap(), Language::Japanese);
+    assert_eq!(
+        create_language("simplified-chinese").unwrap(),
+        Language::SimplifiedChinese
+    );
+    assert!(create_language("klingon").is_err());
 }
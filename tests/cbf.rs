@@ -0,0 +1,71 @@
+use bdk::{
+    bitcoin::{network::constants::Network, Amount},
+    wallet::AddressIndex,
+};
+use bitcoincore_rpc::{Auth, RpcApi};
+use std::env::temp_dir;
+use sweepr::{
+    bip39::parse_mnemonic,
+    network::{create_backend, create_rpc_client},
+    wallet::{
+        check_balance, create_derivation_paths_with_last_index, create_wallet, get_coin_type,
+        get_fee_estimates, sync_wallet,
+    },
+    Error,
+};
+
+/// Needs a local regtest node with a funded wallet loaded, started with
+/// `bitcoind -regtest -blockfilterindex=1 -peerblockfilters=1 -rpcuser=sweepr -rpcpassword=sweepr`.
+#[tokio::test]
+#[ignore]
+async fn test_sync_wallet_with_cbf() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let data_dir = temp_dir().join("sweepr-tests").join("cbf");
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", get_coin_type(Network::Regtest))
+            .unwrap();
+    let mut wallet = create_wallet(
+        (parsed_mnemonic_12, None),
+        Network::Regtest,
+        derivation_path_external,
+        derivation_path_internal,
+        None,
+    )
+    .unwrap();
+    let address = wallet.get_address(AddressIndex::New).address;
+
+    // fund the wallet and confirm the transaction with the node
+    let auth = Auth::UserPass("sweepr".to_string(), "sweepr".to_string());
+    let rpc = create_rpc_client("http://127.0.0.1:18443", auth, None).unwrap();
+    rpc.send_to_address(
+        &address,
+        Amount::from_sat(100_000),
+        None,
+        None,
+        None,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let miner = rpc.get_new_address(None, None).unwrap();
+    rpc.generate_to_address(1, &miner).unwrap();
+
+    let backend = create_backend(
+        "cbf",
+        "127.0.0.1:18444",
+        Network::Regtest,
+        None,
+        Some(&data_dir),
+        None,
+    )
+    .unwrap();
+    sync_wallet(&mut wallet, &backend).await.unwrap();
+
+    assert!(check_balance(&wallet, false));
+    assert!(matches!(
+        get_fee_estimates(&backend, None).await,
+        Err(Error::FeeEstimates(_))
+    ));
+}
@@ -0,0 +1,43 @@
+use clap::Parser;
+use sweepr::{
+    cli::{run_with, Args},
+    Error,
+};
+
+const TXID: &str = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+
+#[tokio::test]
+async fn test_bump_without_mempool() {
+    for backend in ["rpc", "cbf"] {
+        let args = Args::parse_from(["sweepr", "--fee-rate", "10", "-b", backend, "bump", TXID]);
+        assert!(matches!(
+            run_with(args).await,
+            Err(Error::Client(e)) if e.contains("bump")
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_cpfp_without_mempool() {
+    for backend in ["rpc", "cbf"] {
+        let args = Args::parse_from(["sweepr", "--fee-rate", "10", "-b", backend, "cpfp", TXID]);
+        assert!(matches!(
+            run_with(args).await,
+            Err(Error::Client(e)) if e.contains("cpfp")
+        ));
+    }
+}
+
+#[tokio::test]
+async fn test_cbf_without_fee() {
+    let args = Args::parse_from(["sweepr", "-b", "cbf", "-u", "127.0.0.1:9", "--ephemeral"]);
+    assert!(matches!(run_with(args).await, Err(Error::FeeEstimates(_))));
+    let args = Args::parse_from(["sweepr", "-b", "cbf", "--target-blocks", "6", "--ephemeral"]);
+    assert!(matches!(run_with(args).await, Err(Error::FeeEstimates(_))));
+}
+
+#[test]
+fn test_absolute_fee() {
+    assert!(Args::try_parse_from(["sweepr", "--absolute-fee", "0"]).is_err());
+    assert!(Args::try_parse_from(["sweepr", "--absolute-fee", "1"]).is_ok());
+}
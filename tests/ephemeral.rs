@@ -0,0 +1,207 @@
+use bdk::{
+    bitcoin::{
+        consensus::deserialize,
+        hashes::{hex::FromHex, hex::ToHex, sha256, Hash},
+        BlockHash, Network, OutPoint, PackedLockTime, Script, Transaction, TxIn, TxOut, Txid,
+    },
+    wallet::AddressIndex,
+};
+use clap::Parser;
+use std::{
+    env::{set_var, temp_dir},
+    fs::{create_dir_all, read_dir, remove_dir_all},
+    sync::{Arc, Mutex},
+};
+use sweepr::{
+    bip39::parse_mnemonic,
+    cli::{run_with, Args},
+    wallet::{
+        create_address, create_derivation_paths_with_last_index, create_wallet, get_coin_type,
+    },
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+/// The height of the tip of the mock chain.
+const TIP_HEIGHT: u32 = 1_000;
+
+/// A mock Esplora server that knows a single transaction funding a single script.
+struct MockEsplora {
+    tip_hash: BlockHash,
+    scripthash: String,
+    funding_tx: String,
+    broadcasted: Mutex<Vec<String>>,
+}
+
+impl MockEsplora {
+    fn new(script: &Script, funding_tx: &Transaction) -> Self {
+        let tip_hash = BlockHash::hash(b"tip");
+        let funding_tx = format!(
+            r#"{{"txid":"{}","version":1,"locktime":0,"vin":[{{"txid":"{}","vout":0,"prevout":null,"scriptsig":"","witness":[],"sequence":4294967295,"is_coinbase":false}}],"vout":[{{"value":{},"scriptpubkey":"{:x}"}}],"status":{{"confirmed":true,"block_height":{},"block_hash":"{}","block_time":1700000000}},"fee":0,"size":0,"weight":0}}"#,
+            funding_tx.txid(),
+            funding_tx.input[0].previous_output.txid,
+            funding_tx.output[0].value,
+            funding_tx.output[0].script_pubkey,
+            TIP_HEIGHT,
+            tip_hash,
+        );
+        MockEsplora {
+            tip_hash,
+            scripthash: sha256::Hash::hash(script.as_bytes()).into_inner().to_hex(),
+            funding_tx,
+            broadcasted: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// The status and body of the response to a request.
+    fn respond(&self, method: &str, path: &str, body: &str) -> (&'static str, String) {
+        let segments: Vec<&str> = path.split('/').skip(1).collect();
+        match (method, segments.as_slice()) {
+            ("GET", ["blocks", "tip", "height"]) => ("200 OK", TIP_HEIGHT.to_string()),
+            ("GET", ["blocks", "tip", "hash"]) | ("GET", ["block-height", _]) => {
+                ("200 OK", self.tip_hash.to_string())
+            }
+            ("GET", ["scripthash", scripthash, "txs"]) if *scripthash == self.scripthash => {
+                ("200 OK", format!("[{}]", self.funding_tx))
+            }
+            ("GET", ["scripthash", _, "txs"]) => ("200 OK", "[]".to_string()),
+            ("GET", ["fee-estimates"]) => ("200 OK", r#"{"1":2.0,"6":1.0}"#.to_string()),
+            ("POST", ["tx"]) => {
+                self.broadcasted.lock().unwrap().push(body.to_string());
+                ("200 OK", String::new())
+            }
+            _ => ("404 Not Found", String::new()),
+        }
+    }
+}
+
+/// Serve a single request per connection.
+async fn handle(mut socket: TcpStream, esplora: Arc<MockEsplora>) {
+    let mut request = Vec::new();
+    let mut buffer = [0; 4096];
+    let (head, body) = loop {
+        let n = socket.read(&mut buffer).await.unwrap();
+        assert!(n > 0, "connection closed before the request was complete");
+        request.extend_from_slice(&buffer[..n]);
+        let text = String::from_utf8_lossy(&request).to_string();
+        if let Some((head, body)) = text.split_once("\r\n\r\n") {
+            let length: usize = head
+                .lines()
+                .find_map(|line| match line.split_once(':') {
+                    Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                        value.trim().parse().ok()
+                    }
+                    _ => None,
+                })
+                .unwrap_or(0);
+            if body.len() >= length {
+                break (head.to_string(), body.to_string());
+            }
+        }
+    };
+    let mut request_line = head.lines().next().unwrap().split_whitespace();
+    let method = request_line.next().unwrap();
+    let path = request_line.next().unwrap();
+
+    let (status, body) = esplora.respond(method, path, &body);
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    socket.write_all(response.as_bytes()).await.unwrap();
+}
+
+/// Start the mock Esplora server and return its url.
+async fn serve(esplora: Arc<MockEsplora>) -> String {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move {
+        loop {
+            let (socket, _) = listener.accept().await.unwrap();
+            tokio::spawn(handle(socket, esplora.clone()));
+        }
+    });
+    url
+}
+
+#[tokio::test]
+async fn test_ephemeral_sweep() {
+    // every directory a file could be written to by default points to an empty sandbox
+    let sandbox = temp_dir()
+        .join("sweepr-ephemeral-tests")
+        .join(std::process::id().to_string());
+    let _ = remove_dir_all(&sandbox);
+    create_dir_all(&sandbox).unwrap();
+    set_var("HOME", &sandbox);
+    set_var("TMPDIR", &sandbox);
+    set_var("XDG_CACHE_HOME", &sandbox);
+
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", get_coin_type(Network::Regtest))
+            .unwrap();
+    let wallet = create_wallet(
+        (parsed_mnemonic_12, None),
+        Network::Regtest,
+        derivation_path_external,
+        derivation_path_internal,
+        None,
+    )
+    .unwrap();
+
+    let script = wallet
+        .get_address(AddressIndex::Peek(0))
+        .address
+        .script_pubkey();
+    let funding_tx = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint {
+                txid: Txid::all_zeros(),
+                vout: 0,
+            },
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 100_000,
+            script_pubkey: script.clone(),
+        }],
+    };
+    let esplora = Arc::new(MockEsplora::new(&script, &funding_tx));
+    let url = serve(esplora.clone()).await;
+
+    // a full sweep through the command line, which picks the data directory and records the sweep
+    let address = create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Regtest).unwrap();
+    let args = Args::parse_from([
+        "sweepr",
+        "--ephemeral",
+        "--yes",
+        "--network",
+        "regtest",
+        "--backend",
+        "esplora",
+        "--url",
+        &url,
+        mnemonic_12,
+        &address.to_string(),
+    ]);
+    run_with(args).await.unwrap();
+
+    let broadcasted = esplora.broadcasted.lock().unwrap();
+    assert_eq!(broadcasted.len(), 1);
+    let tx: Transaction = deserialize(&Vec::<u8>::from_hex(&broadcasted[0]).unwrap()).unwrap();
+    assert_eq!(tx.input.len(), 1);
+    assert_eq!(tx.input[0].previous_output.txid, funding_tx.txid());
+    assert_eq!(tx.output.len(), 1);
+    assert_eq!(tx.output[0].script_pubkey, address.script_pubkey());
+    assert!(tx.output[0].value < 100_000);
+
+    assert!(read_dir(&sandbox).unwrap().next().is_none());
+    remove_dir_all(&sandbox).unwrap();
+}
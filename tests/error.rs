@@ -0,0 +1,45 @@
+use bdk::bitcoin::Network;
+use sweepr::{bip39::parse_mnemonic, network::create_network, wallet::create_address, Error};
+
+#[test]
+fn test_error_exit_codes() {
+    let errors = [
+        Error::Mnemonic(String::new()),
+        Error::Network(String::new()),
+        Error::Client(String::new()),
+        Error::DerivationPath(String::new()),
+        Error::Descriptor(String::new()),
+        Error::Wallet(String::new()),
+        Error::Address(String::new()),
+        Error::Sync(String::new()),
+        Error::FeeEstimates(String::new()),
+        Error::Transaction(String::new()),
+        Error::Signing(String::new()),
+        Error::Broadcast(String::new()),
+        Error::Io(String::new()),
+        Error::Aborted,
+    ];
+    let mut exit_codes: Vec<i32> = errors.iter().map(Error::exit_code).collect();
+    exit_codes.sort();
+    exit_codes.dedup();
+    assert_eq!(exit_codes.len(), errors.len());
+    assert!(!exit_codes.contains(&0));
+    assert!(!exit_codes.contains(&1));
+    // clap exits with 2 on usage errors
+    assert!(!exit_codes.contains(&2));
+}
+
+#[test]
+fn test_error_messages() {
+    let error = create_network("invalid").unwrap_err();
+    assert_eq!(error, Error::Network("invalid".to_string()));
+    assert_eq!(error.to_string(), "Invalid network: invalid");
+    assert!(matches!(
+        parse_mnemonic("invalid", None),
+        Err(Error::Mnemonic(_))
+    ));
+    assert!(matches!(
+        create_address("invalid", Network::Bitcoin),
+        Err(Error::Address(_))
+    ));
+}
@@ -1,7 +1,12 @@
 use bdk::bitcoin::network::constants::Network;
 use bdk_esplora::esplora_client::AsyncClient;
+use bitcoincore_rpc::Auth;
 use core::any::TypeId;
-use sweepr::network::{create_client, create_network};
+use std::env::temp_dir;
+use sweepr::{
+    network::{create_backend, create_client, create_network, require_tor, Backend},
+    Error,
+};
 
 fn is_esplorablockchain<T: ?Sized + 'static>(_s: &T) -> bool {
     TypeId::of::<AsyncClient>() == TypeId::of::<T>()
@@ -9,25 +14,172 @@ fn is_esplorablockchain<T: ?Sized + 'static>(_s: &T) -> bool {
 
 #[test]
 fn test_network() {
-    assert_eq!(create_network("mainnet"), Network::Bitcoin);
-    assert_eq!(create_network("testnet"), Network::Testnet);
-    assert_eq!(create_network("regtest"), Network::Regtest);
-    assert_eq!(create_network("Mainnet"), Network::Bitcoin);
+    assert_eq!(create_network("mainnet").unwrap(), Network::Bitcoin);
+    assert_eq!(create_network("testnet").unwrap(), Network::Testnet);
+    assert_eq!(create_network("signet").unwrap(), Network::Signet);
+    assert_eq!(create_network("regtest").unwrap(), Network::Regtest);
+    assert_eq!(create_network("Mainnet").unwrap(), Network::Bitcoin);
 }
 
 #[test]
-#[should_panic]
 fn test_invalid_network() {
-    create_network("invalid");
+    assert!(create_network("invalid").is_err());
 }
 
 #[test]
 fn test_create_blockchain() {
-    assert!(is_esplorablockchain(&create_client(
-        "https://mempool.space/api",
-    )));
-    assert!(is_esplorablockchain(&create_client(
-        "https://mempool.space/testnet/api",
-    )));
-    assert!(is_esplorablockchain(&create_client("localhost:3000/api",)));
+    assert!(is_esplorablockchain(
+        &create_client("https://mempool.space/api", None).unwrap()
+    ));
+    assert!(is_esplorablockchain(
+        &create_client("https://mempool.space/testnet/api", None).unwrap()
+    ));
+    assert!(is_esplorablockchain(
+        &create_client("localhost:3000/api", None).unwrap()
+    ));
+}
+
+#[test]
+fn test_create_backend() {
+    assert!(matches!(
+        create_backend(
+            "esplora",
+            "https://mempool.space/api",
+            Network::Bitcoin,
+            None,
+            None,
+            None
+        )
+        .unwrap(),
+        Backend::Esplora(_)
+    ));
+    assert!(matches!(
+        create_backend(
+            "Esplora",
+            "https://mempool.space/api",
+            Network::Bitcoin,
+            None,
+            None,
+            None
+        )
+        .unwrap(),
+        Backend::Esplora(_)
+    ));
+    assert!(matches!(
+        create_backend(
+            "invalid",
+            "https://mempool.space/api",
+            Network::Bitcoin,
+            None,
+            None,
+            None
+        ),
+        Err(Error::Client(_))
+    ));
+}
+
+#[test]
+fn test_create_rpc_backend() {
+    let auth = Auth::UserPass("user".to_string(), "password".to_string());
+    assert!(matches!(
+        create_backend(
+            "rpc",
+            "http://localhost:18443",
+            Network::Regtest,
+            Some(auth),
+            None,
+            None
+        )
+        .unwrap(),
+        Backend::Rpc(_)
+    ));
+    assert!(matches!(
+        create_backend(
+            "rpc",
+            "http://localhost:18443",
+            Network::Regtest,
+            None,
+            None,
+            None
+        ),
+        Err(Error::Client(_))
+    ));
+}
+
+#[test]
+fn test_create_cbf_backend() {
+    assert!(matches!(
+        create_backend("cbf", "127.0.0.1:18444", Network::Regtest, None, None, None),
+        Err(Error::Client(_))
+    ));
+}
+
+#[test]
+fn test_create_backend_with_proxy() {
+    let onion = "http://explorerzydxu5ecjrkwceayqybizmpjjznk5izmitf2modhcusuqlid.onion/api";
+    let proxy = Some("socks5h://127.0.0.1:9050");
+    assert!(matches!(
+        create_backend("esplora", onion, Network::Bitcoin, None, None, proxy).unwrap(),
+        Backend::Esplora(_)
+    ));
+    assert!(matches!(
+        create_backend("esplora", onion, Network::Bitcoin, None, None, None),
+        Err(Error::Client(_))
+    ));
+    assert!(matches!(
+        create_backend(
+            "esplora",
+            "https://mempool.space/api",
+            Network::Bitcoin,
+            None,
+            None,
+            Some("http://127.0.0.1:8080")
+        ),
+        Err(Error::Client(_))
+    ));
+    assert!(matches!(
+        create_backend(
+            "cbf",
+            "127.0.0.1:18444",
+            Network::Regtest,
+            None,
+            Some(&temp_dir()),
+            proxy
+        ),
+        Err(Error::Client(_))
+    ));
+}
+
+#[test]
+fn test_create_rpc_backend_with_proxy() {
+    let proxy = Some("socks5h://127.0.0.1:9050");
+    let auth = || Some(Auth::UserPass("user".to_string(), "password".to_string()));
+    // the host names would be resolved locally, not by the proxy, so they fail before any lookup
+    assert!(matches!(
+        create_backend("rpc", "http://x.onion:8332", Network::Bitcoin, auth(), None, proxy),
+        Err(Error::Client(e)) if e.contains("x.onion")
+    ));
+    assert!(matches!(
+        create_backend("rpc", "http://node.invalid:8332", Network::Bitcoin, auth(), None, proxy),
+        Err(Error::Client(e)) if e.contains("node.invalid")
+    ));
+    assert!(matches!(
+        create_backend(
+            "rpc",
+            "http://10.0.0.2:8332",
+            Network::Bitcoin,
+            auth(),
+            None,
+            proxy
+        )
+        .unwrap(),
+        Backend::Rpc(_)
+    ));
+}
+
+#[test]
+fn test_require_tor() {
+    assert!(require_tor(Some("socks5h://127.0.0.1:9050")).is_ok());
+    assert!(require_tor(Some("socks5://127.0.0.1:9050")).is_err());
+    assert!(require_tor(None).is_err());
 }
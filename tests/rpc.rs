@@ -0,0 +1,72 @@
+use bdk::{
+    bitcoin::{network::constants::Network, Amount},
+    wallet::AddressIndex,
+};
+use bitcoincore_rpc::{Auth, RpcApi};
+use sweepr::{
+    bip39::parse_mnemonic,
+    network::{create_backend, create_rpc_client},
+    wallet::{create_derivation_paths_with_last_index, create_wallet, get_coin_type, sync_wallets},
+};
+
+/// Needs a local regtest node with a funded wallet loaded, started with
+/// `bitcoind -regtest -rpcuser=sweepr -rpcpassword=sweepr`.
+#[tokio::test]
+#[ignore]
+async fn test_sync_wallets_with_rpc() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let mut wallets = ["m/84'/0'/0'/", "m/44'/0'/0'/"]
+        .iter()
+        .map(|path| {
+            let (derivation_path_external, derivation_path_internal) =
+                create_derivation_paths_with_last_index(path, get_coin_type(Network::Regtest))
+                    .unwrap();
+            create_wallet(
+                (parse_mnemonic(mnemonic_12, None).unwrap(), None),
+                Network::Regtest,
+                derivation_path_external,
+                derivation_path_internal,
+                None,
+            )
+            .unwrap()
+        })
+        .collect::<Vec<_>>();
+
+    // fund every wallet and confirm the transactions with the node
+    let auth = Auth::UserPass("sweepr".to_string(), "sweepr".to_string());
+    let rpc = create_rpc_client("http://127.0.0.1:18443", auth.clone(), None).unwrap();
+    for wallet in &mut wallets {
+        let address = wallet.get_address(AddressIndex::New).address;
+        rpc.send_to_address(
+            &address,
+            Amount::from_sat(100_000),
+            None,
+            None,
+            None,
+            None,
+            None,
+            None,
+        )
+        .unwrap();
+    }
+    let miner = rpc.get_new_address(None, None).unwrap();
+    rpc.generate_to_address(1, &miner).unwrap();
+
+    // a single scan of the UTXO set finds the coins of both wallets
+    let backend = create_backend(
+        "rpc",
+        "http://127.0.0.1:18443",
+        Network::Regtest,
+        Some(auth),
+        None,
+        None,
+    )
+    .unwrap();
+    sync_wallets(wallets.iter_mut().collect(), &backend)
+        .await
+        .unwrap();
+
+    for wallet in &wallets {
+        assert!(wallet.get_balance().confirmed >= 100_000);
+    }
+}
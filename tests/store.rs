@@ -0,0 +1,78 @@
+use bdk::{
+    bitcoin::{hashes::Hash, network::constants::Network, secp256k1::Secp256k1, Txid},
+    descriptor::{ExtendedDescriptor, IntoWalletDescriptor},
+};
+use std::env::temp_dir;
+use sweepr::{
+    bip39::parse_mnemonic,
+    store::{open_store, read_last_sweep, store_path, write_last_sweep, WalletStore},
+    wallet::{create_derivation_path, create_descriptor},
+};
+
+fn get_descriptor(mnemonic: &str, network: Network) -> ExtendedDescriptor {
+    let secp = Secp256k1::new();
+    let parsed_mnemonic = parse_mnemonic(mnemonic, None).unwrap();
+    let derivation_path = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let (descriptor, _keymap) = create_descriptor((parsed_mnemonic, None), derivation_path)
+        .unwrap()
+        .into_wallet_descriptor(&secp, network)
+        .unwrap();
+    descriptor
+}
+
+#[test]
+fn test_store_path() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let data_dir = temp_dir().join("sweepr-tests");
+
+    let descriptor_12 = get_descriptor(mnemonic_12, Network::Bitcoin);
+    let descriptor_24 = get_descriptor(mnemonic_24, Network::Bitcoin);
+    let descriptor_12_regtest = get_descriptor(mnemonic_12, Network::Regtest);
+
+    let path_12 = store_path(&data_dir, Network::Bitcoin, &descriptor_12).unwrap();
+    let path_24 = store_path(&data_dir, Network::Bitcoin, &descriptor_24).unwrap();
+    let path_12_regtest = store_path(&data_dir, Network::Regtest, &descriptor_12_regtest).unwrap();
+
+    assert!(path_12.starts_with(&data_dir));
+    assert_eq!(
+        path_12,
+        store_path(&data_dir, Network::Bitcoin, &descriptor_12).unwrap()
+    );
+    assert_ne!(path_12, path_24);
+    assert_ne!(path_12, path_12_regtest);
+}
+
+#[test]
+fn test_open_store() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let data_dir = temp_dir().join("sweepr-tests").join("open-store");
+    let descriptor = get_descriptor(mnemonic_12, Network::Bitcoin);
+
+    open_store(Some(&data_dir), Network::Bitcoin, &descriptor).unwrap();
+
+    assert!(store_path(&data_dir, Network::Bitcoin, &descriptor)
+        .unwrap()
+        .exists());
+}
+
+#[test]
+fn test_open_store_in_memory() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let descriptor = get_descriptor(mnemonic_12, Network::Bitcoin);
+
+    let store = open_store(None, Network::Bitcoin, &descriptor).unwrap();
+
+    assert!(matches!(store, WalletStore::Memory(_)));
+}
+
+#[test]
+fn test_last_sweep() {
+    let data_dir = temp_dir().join("sweepr-tests").join("last-sweep");
+    let txids = vec![Txid::hash(b"first"), Txid::hash(b"second")];
+
+    write_last_sweep(&data_dir, Network::Regtest, &txids).unwrap();
+
+    assert_eq!(read_last_sweep(&data_dir, Network::Regtest).unwrap(), txids);
+    assert!(read_last_sweep(&data_dir, Network::Signet).is_err());
+}
@@ -1,23 +1,43 @@
 use bdk::bitcoin::{BlockHash, Network, PackedLockTime, Transaction, TxOut, Txid};
 use bdk::keys::bip39::Mnemonic;
-use bdk::wallet::{AddressIndex, ChangeSet, Wallet};
+use bdk::wallet::{AddressIndex, Wallet};
 use bdk_chain::bitcoin::util::bip32::DerivationPath;
 use bdk_chain::{bitcoin::hashes::Hash, BlockId, ConfirmationTime};
-use bdk_file_store::Store;
-use sweepr::wallet::create_wallet;
+use sweepr::{
+    network::{create_backend, Backend},
+    store::WalletStore,
+    wallet::create_wallet,
+};
 
-/// Return a fake wallet that appears to be funded for testing.
+/// Return a backend for the tests that never query it, such as sweeps without a confirmation target,
+/// pointing to a local url so that no test depends on a third-party server.
+pub fn get_unused_backend() -> Backend {
+    create_backend(
+        "esplora",
+        "http://127.0.0.1:9/api",
+        Network::Bitcoin,
+        None,
+        None,
+        None,
+    )
+    .unwrap()
+}
+
+/// Return a fake wallet that appears to be funded for testing, kept in memory
+/// so that the tests running in parallel never share a store.
 pub fn get_funded_wallet_with_change<'a>(
     mnemonic: Mnemonic,
     derivation_path_external: DerivationPath,
     derivation_path_internal: DerivationPath,
-) -> (Wallet<Store<'a, ChangeSet>>, Txid) {
+) -> (Wallet<WalletStore<'a>>, Txid) {
     let mut wallet = create_wallet(
-        mnemonic,
+        (mnemonic, None),
         Network::Regtest,
         derivation_path_external,
         derivation_path_internal,
-    );
+        None,
+    )
+    .unwrap();
 
     let address = wallet.get_address(AddressIndex::New).address;
 
@@ -2,31 +2,47 @@ mod utils;
 
 use bdk::{
     bitcoin::{
+        hashes::Hash,
         network::constants::Network,
-        util::{bip32::DerivationPath, psbt::PartiallySignedTransaction},
+        secp256k1::Secp256k1,
+        util::{
+            bip32::{DerivationPath, ExtendedPrivKey, ExtendedPubKey},
+            psbt::PartiallySignedTransaction,
+        },
+        Address, BlockHash, OutPoint, PackedLockTime, Transaction, TxIn, TxOut, Txid,
     },
-    wallet::{ChangeSet, Wallet},
+    wallet::{AddressIndex, Wallet},
 };
-use bdk_file_store::Store;
-use std::any::TypeId;
+use bdk_chain::{BlockId, ConfirmationTime};
+use bitcoincore_rpc::Auth;
+use std::{any::TypeId, collections::HashMap};
 use sweepr::{
     bip39::parse_mnemonic,
-    network::{create_client, create_network},
+    network::{create_backend, create_network},
+    store::WalletStore,
     wallet::{
-        create_address, create_derivation_path, create_derivation_paths_with_last_index,
-        create_signed_transaction, create_wallet, get_fee_estimates,
+        address_warning, below_fee, check_balance, check_destinations, cpfp_fee, create_address,
+        create_consolidated_transaction, create_cpfp_transaction,
+        create_cpfp_transaction_with_parent_fee, create_derivation_path,
+        create_derivation_paths_with_last_index, create_destination, create_destination_wallet,
+        create_fee_bump_transaction, create_payee, create_signed_transaction, create_wallet,
+        derive_destinations, electrum_fee_estimates, get_account, get_coin_type, get_fee_estimates,
+        get_script_type, has_history, is_uneconomical, left_behind, select_fee_estimate,
+        sweepable_coins, with_account, Destination, FeeStrategy, Payee, ScriptType, Share, Sweep,
+        MIN_RELAY_FEE_RATE,
     },
+    Error,
 };
-use utils::get_funded_wallet_with_change;
+use utils::{get_funded_wallet_with_change, get_unused_backend};
 
 #[cfg(feature = "test-sync")]
-use sweepr::wallet::{check_balance, sync_wallet};
+use sweepr::wallet::sync_wallet;
 
 fn is_derivationpath<T: ?Sized + 'static>(_s: &T) -> bool {
     TypeId::of::<DerivationPath>() == TypeId::of::<T>()
 }
 fn is_wallet<T: ?Sized + 'static>(_s: &T) -> bool {
-    TypeId::of::<Wallet<Store<ChangeSet>>>() == TypeId::of::<T>()
+    TypeId::of::<Wallet<WalletStore>>() == TypeId::of::<T>()
 }
 #[cfg(feature = "test-sync")]
 fn is_bool<T: ?Sized + 'static>(_s: &T) -> bool {
@@ -39,81 +55,268 @@ fn is_psbt<T: ?Sized + 'static>(_s: &T) -> bool {
 
 #[test]
 fn test_create_derivation_path() {
-    assert!(is_derivationpath(&create_derivation_path("m/44'/0'/0'/0")));
-    assert!(is_derivationpath(&create_derivation_path("m/44'/0'/0'/1")));
-    assert!(is_derivationpath(&create_derivation_path("m/44h/0h/0h/0")));
-    assert!(is_derivationpath(&create_derivation_path("m/49'/0'/0'/0")));
-    assert!(is_derivationpath(&create_derivation_path("m/49'/0'/0'/1")));
-    assert!(is_derivationpath(&create_derivation_path("m/49h/0h/0h/0")));
-    assert!(is_derivationpath(&create_derivation_path("m/84'/0'/0'/0")));
-    assert!(is_derivationpath(&create_derivation_path("m/84'/0'/0'/1")));
-    assert!(is_derivationpath(&create_derivation_path("m/84h/0h/0h/0")));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/44'/0'/0'/0").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/44'/0'/0'/1").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/44h/0h/0h/0").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/49'/0'/0'/0").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/49'/0'/0'/1").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/49h/0h/0h/0").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/84'/0'/0'/0").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/84'/0'/0'/1").unwrap()
+    ));
+    assert!(is_derivationpath(
+        &create_derivation_path("m/84h/0h/0h/0").unwrap()
+    ));
 }
 
 #[test]
-#[should_panic]
 fn test_invalid_derivation_path() {
-    create_derivation_path("Hello!");
+    assert!(create_derivation_path("Hello!").is_err());
 }
 
 #[test]
 fn test_create_derivation_paths_with_last_index() {
     let (derivation_path_external, derivation_path_internal) =
-        create_derivation_paths_with_last_index("m/44'/0'/0'/");
+        create_derivation_paths_with_last_index("m/44'/0'/0'/", 0).unwrap();
     assert!(is_derivationpath(&derivation_path_external));
     assert!(is_derivationpath(&derivation_path_internal));
 }
 
+#[test]
+fn test_create_derivation_paths_with_coin_type() {
+    assert_eq!(get_coin_type(Network::Bitcoin), 0);
+    assert_eq!(get_coin_type(Network::Testnet), 1);
+    assert_eq!(get_coin_type(Network::Signet), 1);
+    assert_eq!(get_coin_type(Network::Regtest), 1);
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 1).unwrap();
+    assert_eq!(
+        derivation_path_external,
+        create_derivation_path("m/84'/1'/0'/0").unwrap()
+    );
+    assert_eq!(
+        derivation_path_internal,
+        create_derivation_path("m/84'/1'/0'/1").unwrap()
+    );
+
+    let (derivation_path_external, _) =
+        create_derivation_paths_with_last_index("m/84'/0'/2147483646'/", 0).unwrap();
+    assert_eq!(
+        derivation_path_external,
+        create_derivation_path("m/84'/0'/2147483646'/0").unwrap()
+    );
+}
+
+#[test]
+fn test_get_and_set_account() {
+    let derivation_path = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let samourai_derivation_path = create_derivation_path("m/84'/0'/2147483646'/0").unwrap();
+    assert_eq!(get_account(&derivation_path), Some(0));
+    assert_eq!(get_account(&samourai_derivation_path), Some(2147483646));
+    assert_eq!(get_account(&create_derivation_path("m/84'").unwrap()), None);
+
+    let next_derivation_path = with_account(&derivation_path, 1).unwrap();
+    assert_eq!(
+        next_derivation_path,
+        create_derivation_path("m/84'/0'/1'/0").unwrap()
+    );
+    assert_eq!(get_account(&next_derivation_path), Some(1));
+    assert!(with_account(&create_derivation_path("m/84'").unwrap(), 1).is_err());
+}
+
+#[test]
+fn test_has_history() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 1).unwrap();
+
+    let wallet = create_wallet(
+        (parsed_mnemonic_24.clone(), None),
+        Network::Regtest,
+        derivation_path_external.clone(),
+        derivation_path_internal.clone(),
+        None,
+    )
+    .unwrap();
+    assert!(!has_history(&wallet));
+
+    let (funded_wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+    assert!(has_history(&funded_wallet));
+}
+
+#[test]
+fn test_get_script_type() {
+    let legacy = create_derivation_path("m/44'/0'/0'/0").unwrap();
+    let bip47 = create_derivation_path("m/47'/0'/0'/0").unwrap();
+    let nested_segwit = create_derivation_path("m/49'/0'/0'/0").unwrap();
+    let segwit = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let taproot = create_derivation_path("m/86'/0'/0'/0").unwrap();
+    let multisig = create_derivation_path("m/48'/0'/0'/0").unwrap();
+    assert_eq!(get_script_type(&legacy), ScriptType::Legacy);
+    assert_eq!(get_script_type(&bip47), ScriptType::Legacy);
+    assert_eq!(get_script_type(&nested_segwit), ScriptType::NestedSegwit);
+    assert_eq!(get_script_type(&segwit), ScriptType::Segwit);
+    assert_eq!(get_script_type(&taproot), ScriptType::Taproot);
+    assert_eq!(get_script_type(&multisig), ScriptType::Segwit);
+}
+
+#[test]
+fn test_create_wallet_script_types() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let mainnet = create_network("mainnet").unwrap();
+
+    let expected = [
+        ("m/44'/0'/0'/", "1"),
+        ("m/49'/0'/0'/", "3"),
+        ("m/84'/0'/0'/", "bc1q"),
+        ("m/86'/0'/0'/", "bc1p"),
+    ];
+    for (path, prefix) in expected {
+        let (derivation_path_external, derivation_path_internal) =
+            create_derivation_paths_with_last_index(path, 0).unwrap();
+        let mut wallet = create_wallet(
+            (parsed_mnemonic_12.clone(), None),
+            mainnet,
+            derivation_path_external,
+            derivation_path_internal,
+            None,
+        )
+        .unwrap();
+        let address = wallet.get_address(AddressIndex::Peek(0)).address;
+        assert!(address.to_string().starts_with(prefix));
+    }
+}
+
+#[test]
+fn test_create_wallet_with_passphrase() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let mainnet = create_network("mainnet").unwrap();
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+
+    let mut wallet_without_passphrase = create_wallet(
+        (parsed_mnemonic_12.clone(), None),
+        mainnet,
+        derivation_path_external.clone(),
+        derivation_path_internal.clone(),
+        None,
+    )
+    .unwrap();
+    let mut wallet_empty_passphrase = create_wallet(
+        (parsed_mnemonic_12.clone(), Some(String::new())),
+        mainnet,
+        derivation_path_external.clone(),
+        derivation_path_internal.clone(),
+        None,
+    )
+    .unwrap();
+    let mut wallet_with_passphrase = create_wallet(
+        (parsed_mnemonic_12, Some("TREZOR".to_string())),
+        mainnet,
+        derivation_path_external,
+        derivation_path_internal,
+        None,
+    )
+    .unwrap();
+
+    let address_without_passphrase = wallet_without_passphrase
+        .get_address(AddressIndex::Peek(0))
+        .address;
+    let address_empty_passphrase = wallet_empty_passphrase
+        .get_address(AddressIndex::Peek(0))
+        .address;
+    let address_with_passphrase = wallet_with_passphrase
+        .get_address(AddressIndex::Peek(0))
+        .address;
+    assert_eq!(address_without_passphrase, address_empty_passphrase);
+    assert_ne!(address_without_passphrase, address_with_passphrase);
+}
+
 #[test]
 fn test_create_wallet() {
     let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon cactus";
     let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
-    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12);
-    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24);
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
 
-    let mainnet = create_network("mainnet");
-    let testnet = create_network("testnet");
-    let regtest = create_network("regtest");
+    let mainnet = create_network("mainnet").unwrap();
+    let testnet = create_network("testnet").unwrap();
+    let regtest = create_network("regtest").unwrap();
 
-    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0");
-    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1");
+    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1").unwrap();
 
     let wallet_mainnet_12 = create_wallet(
-        parsed_mnemonic_12.clone(),
+        (parsed_mnemonic_12.clone(), None),
         mainnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let wallet_mainnet_24 = create_wallet(
-        parsed_mnemonic_24.clone(),
+        (parsed_mnemonic_24.clone(), None),
         mainnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let wallet_testnet_12 = create_wallet(
-        parsed_mnemonic_12.clone(),
+        (parsed_mnemonic_12.clone(), None),
         testnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let wallet_testnet_24 = create_wallet(
-        parsed_mnemonic_24.clone(),
+        (parsed_mnemonic_24.clone(), None),
         testnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let wallet_regtest_12 = create_wallet(
-        parsed_mnemonic_12.clone(),
+        (parsed_mnemonic_12.clone(), None),
         regtest.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let wallet_regtest_24 = create_wallet(
-        parsed_mnemonic_24.clone(),
+        (parsed_mnemonic_24.clone(), None),
         regtest.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
 
     assert!(is_wallet(&wallet_mainnet_12));
     assert!(is_wallet(&wallet_mainnet_24));
@@ -134,35 +337,59 @@ fn test_create_wallet() {
 #[cfg(feature = "test-sync")]
 async fn test_sync_wallet_and_check_balance() {
     let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
-    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24);
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
 
-    let mainnet = create_network("mainnet");
-    let testnet = create_network("testnet");
+    let mainnet = create_network("mainnet").unwrap();
+    let testnet = create_network("testnet").unwrap();
 
-    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0");
-    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1");
+    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1").unwrap();
 
     let mut wallet_mainnet_24 = create_wallet(
-        parsed_mnemonic_24.clone(),
+        (parsed_mnemonic_24.clone(), None),
         mainnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
     let mut wallet_testnet_24 = create_wallet(
-        parsed_mnemonic_24.clone(),
+        (parsed_mnemonic_24.clone(), None),
         testnet.clone(),
         derivation_path_external.clone(),
         derivation_path_internal.clone(),
-    );
+        None,
+    )
+    .unwrap();
 
-    let esplora_mainnet = create_client("https://mempool.space/api");
-    let esplora_testnet = create_client("https://mempool.space/testnet/api");
+    let esplora_mainnet = create_backend(
+        "esplora",
+        "https://mempool.space/api",
+        Network::Bitcoin,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let esplora_testnet = create_backend(
+        "esplora",
+        "https://mempool.space/testnet/api",
+        Network::Testnet,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
 
-    sync_wallet(&mut wallet_mainnet_24, &esplora_mainnet).await;
-    sync_wallet(&mut wallet_testnet_24, &esplora_testnet).await;
+    sync_wallet(&mut wallet_mainnet_24, &esplora_mainnet)
+        .await
+        .unwrap();
+    sync_wallet(&mut wallet_testnet_24, &esplora_testnet)
+        .await
+        .unwrap();
 
-    let balance_mainnet = check_balance(&wallet_mainnet_24);
-    let balance_testnet = check_balance(&wallet_testnet_24);
+    let balance_mainnet = check_balance(&wallet_mainnet_24, false);
+    let balance_testnet = check_balance(&wallet_testnet_24, false);
 
     assert!(is_bool(&balance_mainnet));
     assert!(is_bool(&balance_testnet));
@@ -170,47 +397,1144 @@ async fn test_sync_wallet_and_check_balance() {
 
 #[test]
 fn test_create_address() {
-    let address_mainnet = create_address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
-    let address_testnet = create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn");
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let address_testnet =
+        create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Testnet).unwrap();
+    let address_legacy =
+        create_address("1BvBMSEYstWetqTFn5Au4m4GFg7xJaNVN2", Network::Bitcoin).unwrap();
+    let address_script =
+        create_address("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", Network::Bitcoin).unwrap();
+    let address_taproot = create_address(
+        "bc1p5d7rjq7g6rdk2yhzks9smlaqtedr4dekq08ge8ztwac72sfr9rusxg3297",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let testnet_on_mainnet = create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Bitcoin);
+    let mainnet_on_testnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Testnet,
+    );
+    let xpub_on_testnet = create_payee("xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V", Network::Testnet);
+
     assert!(address_mainnet.is_valid_for_network(Network::Bitcoin));
     assert!(address_testnet.is_valid_for_network(Network::Testnet));
+    assert!(create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Regtest).is_ok());
+    assert!(matches!(testnet_on_mainnet, Err(Error::Address(_))));
+    assert!(matches!(mainnet_on_testnet, Err(Error::Address(_))));
+    assert!(matches!(xpub_on_testnet, Err(Error::Descriptor(_))));
+    assert_eq!(address_warning(&address_mainnet), None);
+    assert_eq!(address_warning(&address_taproot), None);
+    assert!(address_warning(&address_legacy).is_some());
+    assert!(address_warning(&address_script).is_some());
 }
 
 #[tokio::test]
 async fn test_get_fee_estimates() {
-    let esplora_mainnet = create_client("https://mempool.space/api");
-    let esplora_testnet = create_client("https://mempool.space/testnet/api");
-    let fee_estimates_mainnet = get_fee_estimates(&esplora_mainnet, None).await;
-    let fee_estimates_testnet = get_fee_estimates(&esplora_testnet, None).await;
+    let esplora_mainnet = create_backend(
+        "esplora",
+        "https://mempool.space/api",
+        Network::Bitcoin,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let esplora_testnet = create_backend(
+        "esplora",
+        "https://mempool.space/testnet/api",
+        Network::Testnet,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let fee_estimates_mainnet = get_fee_estimates(&esplora_mainnet, None).await.unwrap();
+    let fee_estimates_testnet = get_fee_estimates(&esplora_testnet, None).await.unwrap();
     assert!(fee_estimates_mainnet > 0.0);
     assert!(fee_estimates_testnet > 0.0);
 }
 
+#[test]
+fn test_select_fee_estimate() {
+    let fee_estimates: HashMap<String, f64> =
+        serde_json::from_str(r#"{"1":25.123,"2":20.5,"3":18.0,"6":12.25,"144":1.5,"1008":0.25}"#)
+            .unwrap();
+    // the estimate of the target itself keeps its precision
+    assert_eq!(select_fee_estimate(&fee_estimates, 1).unwrap(), 25.123);
+    assert_eq!(select_fee_estimate(&fee_estimates, 6).unwrap(), 12.25);
+    // missing targets fall back to the nearest lower target
+    assert_eq!(select_fee_estimate(&fee_estimates, 5).unwrap(), 18.0);
+    assert_eq!(select_fee_estimate(&fee_estimates, 100).unwrap(), 12.25);
+    // estimates below the minimum relay fee rate are clamped
+    assert_eq!(
+        select_fee_estimate(&fee_estimates, 1008).unwrap(),
+        MIN_RELAY_FEE_RATE
+    );
+
+    // no target at or below the requested one
+    let fee_estimates: HashMap<String, f64> =
+        serde_json::from_str(r#"{"6":12.25,"144":1.5}"#).unwrap();
+    assert!(matches!(
+        select_fee_estimate(&fee_estimates, 1),
+        Err(Error::FeeEstimates(_))
+    ));
+
+    // no estimates at all
+    let fee_estimates: HashMap<String, f64> = serde_json::from_str("{}").unwrap();
+    assert!(matches!(
+        select_fee_estimate(&fee_estimates, 1),
+        Err(Error::FeeEstimates(_))
+    ));
+}
+
+#[test]
+fn test_electrum_fee_estimates() {
+    // a canned response of blockchain.estimatefee for each target, in BTC/kvB
+    let targets = [1, 2, 3, 6];
+    let response: Vec<f64> = serde_json::from_str("[0.00025123, 0.000205, -1, 0.0001225]").unwrap();
+    let fee_estimates = electrum_fee_estimates(&targets, &response);
+
+    // converted to sat/vB, without the target the server has no estimate for
+    assert_eq!(fee_estimates.len(), 3);
+    assert!((fee_estimates["1"] - 25.123).abs() < 1e-9);
+    assert!((fee_estimates["2"] - 20.5).abs() < 1e-9);
+    assert!((fee_estimates["6"] - 12.25).abs() < 1e-9);
+    assert!(!fee_estimates.contains_key("3"));
+    // which falls back to the nearest lower target
+    assert!((select_fee_estimate(&fee_estimates, 3).unwrap() - 20.5).abs() < 1e-9);
+
+    // no estimate for any target
+    let fee_estimates = electrum_fee_estimates(&targets[..1], &[-1.0]);
+    assert!(fee_estimates.is_empty());
+    assert!(matches!(
+        select_fee_estimate(&fee_estimates, 1),
+        Err(Error::FeeEstimates(_))
+    ));
+}
+
 #[tokio::test]
 async fn test_create_signed_transaction() {
     let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
-    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24);
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0").unwrap();
+    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1").unwrap();
+
+    let (mut wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let address_testnet =
+        create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn", Network::Testnet).unwrap();
+
+    let esplora_mainnet = create_backend(
+        "esplora",
+        "https://mempool.space/api",
+        Network::Bitcoin,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+    let esplora_testnet = create_backend(
+        "esplora",
+        "https://mempool.space/testnet/api",
+        Network::Testnet,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let sweep_mainnet = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.into()],
+        &esplora_mainnet,
+        FeeStrategy::default(),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let sweep_testnet = create_signed_transaction(
+        &mut wallet,
+        &[address_testnet.into()],
+        &esplora_testnet,
+        FeeStrategy::default(),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+
+    assert!(is_psbt(&sweep_mainnet.psbt));
+    assert!(is_psbt(&sweep_testnet.psbt));
+    assert_eq!(sweep_mainnet.amount(), 50_000);
+    assert!(sweep_mainnet.fee() > 0);
+    assert!(sweep_mainnet.vsize() > 0);
+    assert!(sweep_mainnet.fee_rate() > 0.0);
+    assert!(sweep_mainnet
+        .to_string()
+        .contains("Destination: bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"));
+}
+
+#[tokio::test]
+async fn test_create_consolidated_transaction() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
 
-    let derivation_path_external = create_derivation_path("m/84'/0'/0'/0");
-    let derivation_path_internal = create_derivation_path("m/84'/0'/0'/1");
+    let (segwit_external, segwit_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (taproot_external, taproot_internal) =
+        create_derivation_paths_with_last_index("m/86'/0'/0'/", 0).unwrap();
 
+    let (wallet_segwit, _txid) =
+        get_funded_wallet_with_change(parsed_mnemonic_24.clone(), segwit_external, segwit_internal);
+    let (wallet_taproot, _txid) =
+        get_funded_wallet_with_change(parsed_mnemonic_24, taproot_external, taproot_internal);
+    let mut wallets = vec![wallet_segwit, wallet_taproot];
+
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let backend = get_unused_backend();
+
+    let sweep = create_consolidated_transaction(
+        &mut wallets,
+        &[address_mainnet.into()],
+        &backend,
+        FeeStrategy::Rate(5.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+
+    assert!(is_psbt(&sweep.psbt));
+    assert_eq!(sweep.psbt.unsigned_tx.input.len(), 2);
+    assert_eq!(sweep.psbt.unsigned_tx.output.len(), 1);
+    assert_eq!(sweep.amount(), 100_000);
+}
+
+#[tokio::test]
+async fn test_create_signed_transaction_with_fee_strategies() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
     let (mut wallet, _txid) = get_funded_wallet_with_change(
         parsed_mnemonic_24,
         derivation_path_external,
         derivation_path_internal,
     );
 
-    let address_mainnet = create_address("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq");
-    let address_testnet = create_address("mipcBbFg9gMiCh81Kj8tqqdgoZub1ZJRfn");
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let backend = get_unused_backend();
+
+    let sweep_rate = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.clone().into()],
+        &backend,
+        FeeStrategy::Rate(5.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let sweep_absolute = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.clone().into()],
+        &backend,
+        FeeStrategy::Absolute(1_000),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let sweep_above_max = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.into()],
+        &backend,
+        FeeStrategy::Rate(5.0),
+        2.0,
+        false,
+        false,
+    )
+    .await;
+
+    assert!(sweep_rate.fee_rate() >= 5.0);
+    assert!(sweep_rate.fee_rate() < 6.0);
+    assert_eq!(sweep_absolute.fee(), 1_000);
+    assert_eq!(sweep_absolute.amount(), 50_000);
+    assert!(matches!(sweep_above_max, Err(Error::Transaction(_))));
+}
+
+#[tokio::test]
+async fn test_create_fee_bump_transaction() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+    let mut wallets = vec![wallet];
+
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let backend = get_unused_backend();
+
+    let sweep = create_signed_transaction(
+        &mut wallets[0],
+        &[address_mainnet.clone().into()],
+        &backend,
+        FeeStrategy::Rate(2.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let tx = sweep.psbt.clone().extract_tx();
+    let txid = tx.txid();
+    wallets[0]
+        .insert_tx(tx.clone(), ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    let bump = create_fee_bump_transaction(
+        &mut wallets,
+        txid,
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .await
+    .unwrap();
+    let bump_lower = create_fee_bump_transaction(
+        &mut wallets,
+        txid,
+        &backend,
+        FeeStrategy::Rate(1.0),
+        1_000.0,
+    )
+    .await;
+    let bump_unknown = create_fee_bump_transaction(
+        &mut wallets,
+        bump.psbt.unsigned_tx.txid(),
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .await;
+
+    // the same coins go to the same destination, paying a higher fee
+    assert_eq!(bump.psbt.unsigned_tx.input, tx.input);
+    assert_eq!(
+        bump.address.script_pubkey(),
+        address_mainnet.script_pubkey()
+    );
+    assert_eq!(bump.amount(), sweep.amount());
+    assert!(bump.fee() > sweep.fee());
+    assert!(bump.fee_rate() >= 10.0);
+    assert!(matches!(bump_lower, Err(Error::Transaction(_))));
+    assert!(matches!(bump_unknown, Err(Error::Transaction(_))));
+}
+
+#[tokio::test]
+async fn test_create_destination_wallet() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let xprv =
+        ExtendedPrivKey::new_master(Network::Regtest, &parsed_mnemonic_12.to_seed("")).unwrap();
 
-    let esplora_mainnet = create_client("https://mempool.space/api");
-    let esplora_testnet = create_client("https://mempool.space/testnet/api");
+    let mut wallet_descriptor = create_destination_wallet(
+        &format!("wpkh({}/84'/1'/0'/0/*)", xprv),
+        Network::Regtest,
+        None,
+    )
+    .unwrap();
+    let mut wallet_xprv =
+        create_destination_wallet(&xprv.to_string(), Network::Regtest, None).unwrap();
+    let wallet_invalid = create_destination_wallet("invalid", Network::Regtest, None);
 
-    let psbt_mainnet =
-        create_signed_transaction(&mut wallet, address_mainnet, &esplora_mainnet).await;
-    let psbt_testnet =
-        create_signed_transaction(&mut wallet, address_testnet, &esplora_testnet).await;
+    assert!(is_wallet(&wallet_descriptor));
+    assert!(is_wallet(&wallet_xprv));
+    assert!(wallet_descriptor
+        .get_address(AddressIndex::New)
+        .address
+        .to_string()
+        .starts_with("bcrt1q"));
+    assert!(wallet_xprv
+        .get_internal_address(AddressIndex::New)
+        .address
+        .to_string()
+        .starts_with("bcrt1q"));
+    assert!(matches!(wallet_invalid, Err(Error::Descriptor(_))));
 
-    assert!(is_psbt(&psbt_mainnet));
-    assert!(is_psbt(&psbt_testnet));
+    let backend = get_unused_backend();
+    let cpfp_unknown = create_cpfp_transaction(
+        &mut wallet_xprv,
+        Txid::all_zeros(),
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .await;
+    assert!(matches!(cpfp_unknown, Err(Error::Transaction(_))));
+
+    // a watch-only destination cannot sign the child of a sweep paying it
+    let xpub = ExtendedPubKey::from_priv(&Secp256k1::new(), &xprv);
+    let mut wallet_xpub =
+        create_destination_wallet(&xpub.to_string(), Network::Regtest, None).unwrap();
+    let sweep_tx = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::hash(b"sweep"), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 50_000,
+            script_pubkey: wallet_xpub
+                .get_address(AddressIndex::New)
+                .address
+                .script_pubkey(),
+        }],
+    };
+    let sweep_txid = sweep_tx.txid();
+    wallet_xpub
+        .insert_tx(sweep_tx, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+    let cpfp_watch_only = create_cpfp_transaction_with_parent_fee(
+        &mut wallet_xpub,
+        sweep_txid,
+        1_000,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    );
+    assert!(matches!(cpfp_watch_only, Err(Error::Signing(_))));
+}
+
+#[test]
+fn test_create_cpfp_transaction() {
+    let mnemonic_12 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+    let parsed_mnemonic_12 = parse_mnemonic(mnemonic_12, None).unwrap();
+    let xprv =
+        ExtendedPrivKey::new_master(Network::Regtest, &parsed_mnemonic_12.to_seed("")).unwrap();
+    let mut wallet = create_destination_wallet(&xprv.to_string(), Network::Regtest, None).unwrap();
+
+    // an unconfirmed sweep paying the destination, whose fee is known
+    let parent = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::hash(b"sweep"), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 50_000,
+            script_pubkey: wallet
+                .get_address(AddressIndex::New)
+                .address
+                .script_pubkey(),
+        }],
+    };
+    let parent_txid = parent.txid();
+    let parent_vsize = parent.vsize() as u64;
+    wallet
+        .insert_tx(parent, ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    // the child pays for the package to reach the fee rate
+    let parent_fee = 100;
+    let child = create_cpfp_transaction_with_parent_fee(
+        &mut wallet,
+        parent_txid,
+        parent_fee,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .unwrap();
+    let child_vsize = child.vsize() as u64;
+    let package_fee_rate = (parent_fee + child.fee()) as f64 / (parent_vsize + child_vsize) as f64;
+    assert_eq!(child.psbt.unsigned_tx.input.len(), 1);
+    assert_eq!(
+        child.psbt.unsigned_tx.input[0].previous_output.txid,
+        parent_txid
+    );
+    assert!(package_fee_rate >= 10.0);
+    assert!(child.fee() as f64 >= MIN_RELAY_FEE_RATE * child_vsize as f64);
+
+    // a parent already paying enough still needs a child relayed on its own
+    let child_min = create_cpfp_transaction_with_parent_fee(
+        &mut wallet,
+        parent_txid,
+        10_000,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .unwrap();
+    assert!(child_min.fee() as f64 >= MIN_RELAY_FEE_RATE * child_min.vsize() as f64);
+    assert!(child_min.fee() < child.fee());
+
+    // the fee rate of the package is checked against the maximum
+    let child_above_max = create_cpfp_transaction_with_parent_fee(
+        &mut wallet,
+        parent_txid,
+        parent_fee,
+        FeeStrategy::Rate(10.0),
+        5.0,
+    );
+    assert!(matches!(child_above_max, Err(Error::Transaction(_))));
+
+    assert_eq!(cpfp_fee(10.0, 1_000, 200, 110), 2_100);
+    assert_eq!(cpfp_fee(1.0, 10_000, 200, 110), 110);
+}
+
+#[tokio::test]
+async fn test_unconfirmed_and_immature_coins() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (mut wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+
+    // an unconfirmed payment and a coinbase output mined at the tip
+    let script_pubkey = wallet
+        .get_address(AddressIndex::New)
+        .address
+        .script_pubkey();
+    let unconfirmed_tx = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::hash(b"unconfirmed"), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 20_000,
+            script_pubkey: script_pubkey.clone(),
+        }],
+    };
+    let coinbase_tx = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::null(),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 30_000,
+            script_pubkey,
+        }],
+    };
+    wallet
+        .insert_tx(
+            unconfirmed_tx,
+            ConfirmationTime::Unconfirmed { last_seen: 0 },
+        )
+        .unwrap();
+    wallet
+        .insert_tx(
+            coinbase_tx,
+            ConfirmationTime::Confirmed {
+                height: 1_000,
+                time: 100,
+            },
+        )
+        .unwrap();
+
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let backend = get_unused_backend();
+
+    let sweep_confirmed = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.clone().into()],
+        &backend,
+        FeeStrategy::Absolute(1_000),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let sweep_unconfirmed = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.into()],
+        &backend,
+        FeeStrategy::Absolute(1_000),
+        1_000.0,
+        true,
+        false,
+    )
+    .await
+    .unwrap();
+    let left_behind_confirmed = left_behind(&wallet, false, None);
+    let left_behind_unconfirmed = left_behind(&wallet, true, None);
+
+    assert!(check_balance(&wallet, false));
+    assert_eq!(sweep_confirmed.amount(), 50_000);
+    assert_eq!(sweep_unconfirmed.amount(), 70_000);
+    assert_eq!(left_behind_confirmed.immature, 30_000);
+    assert_eq!(left_behind_confirmed.unconfirmed, 20_000);
+    assert_eq!(left_behind_unconfirmed.immature, 30_000);
+    assert_eq!(left_behind_unconfirmed.unconfirmed, 0);
+
+    // the coinbase output matures once its block is 100 blocks deep, counting itself
+    wallet
+        .insert_checkpoint(BlockId {
+            height: 1_098,
+            hash: BlockHash::hash(b"1098"),
+        })
+        .unwrap();
+    assert_eq!(left_behind(&wallet, true, None).immature, 30_000);
+    assert_eq!(sweepable_coins(&wallet, true, None).len(), 2);
+    wallet
+        .insert_checkpoint(BlockId {
+            height: 1_099,
+            hash: BlockHash::hash(b"1099"),
+        })
+        .unwrap();
+    assert_eq!(left_behind(&wallet, true, None).immature, 0);
+    assert_eq!(sweepable_coins(&wallet, true, None).len(), 3);
+}
+
+#[tokio::test]
+async fn test_uneconomical_coins() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (mut wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+
+    // a coin worth less than the cost of spending it at 10 sat/vB
+    let dust_tx = Transaction {
+        version: 1,
+        lock_time: PackedLockTime(0),
+        input: vec![TxIn {
+            previous_output: OutPoint::new(Txid::hash(b"dust"), 0),
+            ..Default::default()
+        }],
+        output: vec![TxOut {
+            value: 300,
+            script_pubkey: wallet
+                .get_address(AddressIndex::New)
+                .address
+                .script_pubkey(),
+        }],
+    };
+    let dust_outpoint = OutPoint::new(dust_tx.txid(), 0);
+    wallet
+        .insert_tx(
+            dust_tx,
+            ConfirmationTime::Confirmed {
+                height: 1_000,
+                time: 100,
+            },
+        )
+        .unwrap();
+
+    let address_mainnet = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let backend = get_unused_backend();
+
+    let sweep_without_dust = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.clone().into()],
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let sweep_with_dust = create_signed_transaction(
+        &mut wallet,
+        &[address_mainnet.into()],
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+        false,
+        true,
+    )
+    .await
+    .unwrap();
+    let dust = wallet
+        .list_unspent()
+        .find(|utxo| utxo.outpoint == dust_outpoint)
+        .unwrap();
+
+    assert!(is_uneconomical(&wallet, &dust, 10.0));
+    assert!(!is_uneconomical(&wallet, &dust, 1.0));
+    assert_eq!(sweepable_coins(&wallet, false, Some(10.0)).len(), 1);
+    assert_eq!(sweepable_coins(&wallet, false, None).len(), 2);
+    assert_eq!(
+        left_behind(&wallet, false, Some(10.0)).dust,
+        vec![(dust_outpoint, 300)]
+    );
+    assert_eq!(sweep_without_dust.amount(), 50_000);
+    assert_eq!(sweep_with_dust.amount(), 50_300);
+}
+
+#[test]
+fn test_below_fee() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+    let address: Destination = create_destination(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let share = create_destination(
+        "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu:49500",
+        Network::Bitcoin,
+    )
+    .unwrap();
+
+    // 50_000 sats cover a fee of 1 sat/vB, but not a fee rate high enough to eat them
+    assert_eq!(
+        below_fee(
+            &[&wallet],
+            &[address.clone()],
+            false,
+            None,
+            FeeStrategy::Rate(1.0)
+        ),
+        None
+    );
+    assert!(below_fee(
+        &[&wallet],
+        &[address.clone()],
+        false,
+        None,
+        FeeStrategy::Rate(500.0)
+    )
+    .is_some());
+    assert_eq!(
+        below_fee(
+            &[&wallet],
+            &[address.clone()],
+            false,
+            None,
+            FeeStrategy::Absolute(49_500)
+        ),
+        Some((50_000, 49_500))
+    );
+    assert!(below_fee(
+        &[&wallet],
+        &[share, address],
+        false,
+        None,
+        FeeStrategy::Rate(1.0)
+    )
+    .is_some());
+}
+
+#[tokio::test]
+async fn test_split_destinations() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (mut wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+
+    let amount = create_destination(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:10000",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let percent = create_destination(
+        "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu:25%",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let remainder =
+        create_destination("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", Network::Bitcoin).unwrap();
+    let destinations = vec![amount.clone(), percent.clone(), remainder.clone()];
+    let backend = get_unused_backend();
+
+    let sweep = create_signed_transaction(
+        &mut wallet,
+        &destinations,
+        &backend,
+        FeeStrategy::Absolute(1_000),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let too_much = create_signed_transaction(
+        &mut wallet,
+        &[
+            create_destination(
+                "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:60000",
+                Network::Bitcoin,
+            )
+            .unwrap(),
+            remainder.clone(),
+        ],
+        &backend,
+        FeeStrategy::Absolute(1_000),
+        1_000.0,
+        false,
+        false,
+    )
+    .await;
+    let value = |address: &Address| {
+        sweep
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .find(|output| output.script_pubkey == address.script_pubkey())
+            .map(|output| output.value)
+    };
+
+    assert_eq!(amount.share, Some(Share::Amount(10_000)));
+    assert_eq!(percent.share, Some(Share::Percent(25.0)));
+    assert_eq!(remainder.share, None);
+    assert!(create_destination(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:0",
+        Network::Bitcoin
+    )
+    .is_err());
+    assert!(create_destination(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:100%",
+        Network::Bitcoin
+    )
+    .is_err());
+    assert!(create_destination(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:abc",
+        Network::Bitcoin
+    )
+    .is_err());
+    assert!(check_destinations(&destinations).is_ok());
+    assert!(check_destinations(&[]).is_err());
+    assert!(check_destinations(&[remainder.clone(), amount.clone()]).is_err());
+    assert!(check_destinations(&[amount.clone(), remainder.clone(), remainder.clone()]).is_err());
+    assert!(check_destinations(&[
+        percent.clone(),
+        percent.clone(),
+        percent.clone(),
+        percent,
+        remainder
+    ])
+    .is_err());
+    assert_eq!(sweep.psbt.unsigned_tx.output.len(), 3);
+    assert_eq!(
+        value(
+            &create_address(
+                "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+                Network::Bitcoin
+            )
+            .unwrap()
+        ),
+        Some(10_000)
+    );
+    assert_eq!(
+        value(
+            &create_address(
+                "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu",
+                Network::Bitcoin
+            )
+            .unwrap()
+        ),
+        Some(12_500)
+    );
+    assert_eq!(value(&sweep.address), Some(26_500));
+    assert_eq!(sweep.recipients.len(), 2);
+    assert_eq!(sweep.amount(), 50_000);
+    assert!(matches!(too_much, Err(Error::Transaction(_))));
+}
+
+#[tokio::test]
+async fn test_fee_bump_split_destinations() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+    let mut wallets = vec![wallet];
+
+    let destinations = vec![
+        create_destination(
+            "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq:10000",
+            Network::Bitcoin,
+        )
+        .unwrap(),
+        create_destination(
+            "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu:25%",
+            Network::Bitcoin,
+        )
+        .unwrap(),
+        create_destination("3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy", Network::Bitcoin).unwrap(),
+    ];
+    let backend = get_unused_backend();
+
+    let sweep = create_signed_transaction(
+        &mut wallets[0],
+        &destinations,
+        &backend,
+        FeeStrategy::Rate(2.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await
+    .unwrap();
+    let tx = sweep.psbt.clone().extract_tx();
+    let txid = tx.txid();
+    wallets[0]
+        .insert_tx(tx.clone(), ConfirmationTime::Unconfirmed { last_seen: 0 })
+        .unwrap();
+
+    let bump = create_fee_bump_transaction(
+        &mut wallets,
+        txid,
+        &backend,
+        FeeStrategy::Rate(10.0),
+        1_000.0,
+    )
+    .await
+    .unwrap();
+    let outputs = |sweep: &Sweep| -> Vec<(Address, u64)> {
+        sweep
+            .psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|txout| {
+                (
+                    Address::from_script(&txout.script_pubkey, Network::Bitcoin).unwrap(),
+                    txout.value,
+                )
+            })
+            .collect()
+    };
+    let remainder = |sweep: &Sweep| outputs(sweep).last().unwrap().clone();
+
+    // the shares keep their order and amounts, and the remainder is the last output
+    assert_eq!(outputs(&sweep)[..2], sweep.recipients[..]);
+    assert_eq!(remainder(&sweep).0, sweep.address);
+    assert_eq!(
+        sweep.address.to_string(),
+        "3J98t1WpEZ73CNmQviecrnyiWrnqRhWNLy"
+    );
+    // the fee increase is taken from the remainder only
+    assert_eq!(bump.recipients, sweep.recipients);
+    assert_eq!(outputs(&bump)[..2], sweep.recipients[..]);
+    assert_eq!(bump.address, sweep.address);
+    assert_eq!(remainder(&bump).0, sweep.address);
+    assert_eq!(
+        remainder(&bump).1,
+        remainder(&sweep).1 - (bump.fee() - sweep.fee())
+    );
+    assert!(bump.fee_rate() >= 10.0);
+}
+
+#[tokio::test]
+async fn test_descriptor_destinations() {
+    let mnemonic_24 = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon art";
+    let parsed_mnemonic_24 = parse_mnemonic(mnemonic_24, None).unwrap();
+
+    let (derivation_path_external, derivation_path_internal) =
+        create_derivation_paths_with_last_index("m/84'/0'/0'/", 0).unwrap();
+    let (mut wallet, _txid) = get_funded_wallet_with_change(
+        parsed_mnemonic_24,
+        derivation_path_external,
+        derivation_path_internal,
+    );
+
+    let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+    let descriptor = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", xpub);
+    let address = create_payee(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let payee_xpub = create_payee(xpub, Network::Bitcoin).unwrap();
+    let payee_descriptor = create_payee(&descriptor, Network::Bitcoin).unwrap();
+    let payee_invalid = create_payee("invalid", Network::Bitcoin);
+    let destination = create_destination(&format!("{}:25%", descriptor), Network::Bitcoin).unwrap();
+
+    let backend = get_unused_backend();
+    let sweep_underived = create_signed_transaction(
+        &mut wallet,
+        &[payee_xpub.clone().into()],
+        &backend,
+        FeeStrategy::Rate(5.0),
+        1_000.0,
+        false,
+        false,
+    )
+    .await;
+
+    assert!(matches!(address, Payee::Address(_)));
+    assert_eq!(payee_xpub, Payee::Descriptor(xpub.to_string()));
+    assert_eq!(payee_descriptor, Payee::Descriptor(descriptor.clone()));
+    assert!(matches!(payee_invalid, Err(Error::Address(_))));
+    assert_eq!(destination.payee, payee_descriptor);
+    assert_eq!(destination.share, Some(Share::Percent(25.0)));
+    assert!(matches!(sweep_underived, Err(Error::Address(_))));
+}
+
+#[tokio::test]
+async fn test_derive_destinations_with_rpc() {
+    let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+    let address = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    // the node is never queried, neither to create the client nor to refuse the descriptor
+    let rpc = create_backend(
+        "rpc",
+        "http://127.0.0.1:9",
+        Network::Bitcoin,
+        Some(Auth::UserPass("user".to_string(), "password".to_string())),
+        None,
+        None,
+    )
+    .unwrap();
+
+    let destinations = vec![create_destination(xpub, Network::Bitcoin).unwrap()];
+    let derived = derive_destinations(&destinations, 1, Network::Bitcoin, None, &rpc).await;
+    assert!(matches!(derived, Err(Error::Address(_))));
+
+    let destinations = vec![Destination::from(address.clone())];
+    let derived = derive_destinations(&destinations, 1, Network::Bitcoin, None, &rpc)
+        .await
+        .unwrap();
+    assert_eq!(derived, vec![vec![Destination::from(address)]]);
+}
+
+#[tokio::test]
+#[cfg(feature = "test-sync")]
+async fn test_derive_destinations() {
+    let xpub = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+    let address = create_address(
+        "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq",
+        Network::Bitcoin,
+    )
+    .unwrap();
+    let destinations = vec![
+        create_destination(&format!("{}:25%", xpub), Network::Bitcoin).unwrap(),
+        create_destination(xpub, Network::Bitcoin).unwrap(),
+        Destination::from(address.clone()),
+    ];
+    let esplora_mainnet = create_backend(
+        "esplora",
+        "https://mempool.space/api",
+        Network::Bitcoin,
+        None,
+        None,
+        None,
+    )
+    .unwrap();
+
+    let derived = derive_destinations(
+        &destinations[..2],
+        2,
+        Network::Bitcoin,
+        None,
+        &esplora_mainnet,
+    )
+    .await
+    .unwrap();
+    let derived_address = derive_destinations(
+        &destinations[2..],
+        2,
+        Network::Bitcoin,
+        None,
+        &esplora_mainnet,
+    )
+    .await
+    .unwrap();
+    let mut payees: Vec<String> = derived
+        .iter()
+        .flatten()
+        .map(|destination| destination.payee.to_string())
+        .collect();
+    payees.sort();
+    payees.dedup();
+
+    assert_eq!(derived.len(), 2);
+    assert!(derived
+        .iter()
+        .flatten()
+        .all(|destination| matches!(destination.payee, Payee::Address(_))));
+    assert_eq!(derived[0][0].share, Some(Share::Percent(25.0)));
+    // the first address of the account has history and is skipped, and no address is reused
+    assert!(!payees.contains(&"bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu".to_string()));
+    assert_eq!(payees.len(), 4);
+    assert_eq!(
+        derived_address,
+        vec![vec![address.clone().into()], vec![address.into()]]
+    );
 }